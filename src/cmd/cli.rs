@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, str::FromStr};
 
 use clap::{Parser, Subcommand};
 
@@ -31,9 +31,62 @@ pub enum Commands {
     /// example: cargo run --release train --from 2022-12-10 --to 2022-12-24
     Train(super::train::TrainArgs),
     Predict(super::predict::PredictArgs),
+    /// example: cargo run --release evaluate --filename data/2023.parquet --walk-forward --layers 16,1
+    Evaluate(super::evaluate::EvaluateArgs),
+    /// example: cargo run --release plot --output week.svg heatmap --month 1
+    Plot(super::plot::PlotArgs),
 }
 
 // Common argument parsing helper functions
 pub fn parse_date(arg: &str) -> Result<chrono::NaiveDate, chrono::ParseError> {
     chrono::NaiveDate::parse_from_str(arg, "%Y-%m-%d")
 }
+
+// An IANA timezone name, e.g. Europe/London
+pub fn parse_timezone(arg: &str) -> Result<chrono_tz::Tz, String> {
+    arg.parse()
+        .map_err(|_| format!("Unknown IANA timezone: {}", arg))
+}
+
+// A probability in (0, 1]
+pub fn parse_keep_probability(arg: &str) -> Result<f64, String> {
+    let p: f64 = arg.parse().map_err(|e| format!("{}", e))?;
+    if p > 0.0 && p <= 1.0 {
+        Ok(p)
+    } else {
+        Err(format!("Keep probability must be in (0, 1]: {}", arg))
+    }
+}
+
+// A fraction of the samples in (0, 1), so both sides of a split can have some
+pub fn parse_fraction(arg: &str) -> Result<f64, String> {
+    let p: f64 = arg.parse().map_err(|e| format!("{}", e))?;
+    if p > 0.0 && p < 1.0 {
+        Ok(p)
+    } else {
+        Err(format!("Fraction must be in (0, 1): {}", arg))
+    }
+}
+
+/// A layer size with an optional activation, e.g. "16" or "16:relu"
+#[derive(Clone, Debug, PartialEq)]
+pub struct LayerSpec {
+    pub size: usize,
+    pub activation: Option<String>,
+}
+
+impl FromStr for LayerSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (size, activation) = match s.split_once(':') {
+            Some((size, activation)) => (size, Some(activation.trim().to_string())),
+            None => (s, None),
+        };
+        let size = size
+            .trim()
+            .parse()
+            .map_err(|e| format!("Invalid layer size in {}: {}", s, e))?;
+        Ok(LayerSpec { size, activation })
+    }
+}
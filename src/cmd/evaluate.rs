@@ -0,0 +1,269 @@
+use chrono::Duration;
+use clap::{Args, ValueEnum};
+use ndarray_rand::rand::{SeedableRng, rngs::StdRng, seq::SliceRandom};
+use serde::Serialize;
+
+use super::{
+    import::{ImportError, read_light_samples},
+    predict::predict_samples,
+    train::{HyperParams, inputs_and_targets},
+};
+use crate::{
+    data::{split::walk_forward, types::LightSample},
+    mlp::{
+        calibration::Calibration,
+        metrics::{Metrics, ThresholdCriterion, best_threshold},
+        mlp::MLP,
+    },
+};
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum CalibrationKind {
+    /// remove any stored calibration
+    None,
+    /// logistic fit to the output's logit
+    Platt,
+    /// non-decreasing step function
+    Isotonic,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum CriterionKind {
+    F1,
+    /// Youden's J (recall + specificity - 1)
+    Youden,
+}
+
+#[derive(Args)]
+pub struct EvaluateArgs {
+    #[arg(short, long)]
+    filename: String,
+    #[arg(short, long, default_value = "data/mlp.json")]
+    mlp_filename: String,
+    /// train a fresh model on each rolling-origin fold of the file instead of loading one,
+    /// testing on the period that follows its training data
+    #[arg(long)]
+    walk_forward: bool,
+    /// length of each walk-forward test period in days
+    #[arg(
+        long,
+        default_value_t = 7,
+        requires = "walk_forward",
+        value_parser = clap::value_parser!(i64).range(1..)
+    )]
+    fold_days: i64,
+    /// number of periods of training data required before the first fold
+    #[arg(long, default_value_t = 1, requires = "walk_forward")]
+    min_train_folds: usize,
+    /// probability above which a light is predicted to be on [default: the model's threshold]
+    #[arg(long)]
+    threshold: Option<f64>,
+    /// fit a calibration on --calibration-file, or remove it with none, and save it in the
+    /// model
+    #[arg(
+        long,
+        value_enum,
+        conflicts_with = "walk_forward",
+        requires_ifs = [("platt", "calibration_file"), ("isotonic", "calibration_file")]
+    )]
+    calibrate: Option<CalibrationKind>,
+    /// choose the threshold that maximises this on --calibration-file (after any
+    /// calibration) and save it in the model
+    #[arg(
+        long,
+        value_enum,
+        conflicts_with_all = ["walk_forward", "threshold"],
+        requires = "calibration_file"
+    )]
+    tune_threshold: Option<CriterionKind>,
+    /// held-out samples, not used for training or in --filename, that --calibrate and
+    /// --tune-threshold fit on so the reported metrics stay out-of-sample
+    #[arg(long)]
+    calibration_file: Option<String>,
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+    #[command(flatten)]
+    hyper: HyperParams,
+}
+
+pub async fn run(args: &EvaluateArgs) -> Result<(), ImportError> {
+    let samples = read_light_samples(&args.filename)?;
+    if args.walk_forward {
+        return run_walk_forward(args, samples);
+    }
+
+    let mut mlp = MLP::load(&args.mlp_filename)?;
+    let targets: Vec<f64> = samples.iter().map(LightSample::on).collect();
+    let text = args.format == OutputFormat::Text;
+
+    let calibration_samples = match &args.calibration_file {
+        Some(filename) => read_light_samples(filename)?,
+        None => vec![],
+    };
+    let calibration_targets: Vec<f64> = calibration_samples.iter().map(LightSample::on).collect();
+    if let Some(kind) = args.calibrate {
+        mlp.set_calibration(Calibration::None);
+        // none only removes the stored calibration, without needing samples to fit on
+        if !matches!(kind, CalibrationKind::None) {
+            let outputs = probabilities(&mlp, &calibration_samples)?;
+            mlp.set_calibration(match kind {
+                CalibrationKind::None => Calibration::None,
+                CalibrationKind::Platt => Calibration::platt(&outputs, &calibration_targets),
+                CalibrationKind::Isotonic => Calibration::isotonic(&outputs, &calibration_targets),
+            });
+        }
+    }
+    if let Some(kind) = args.tune_threshold {
+        let criterion = match kind {
+            CriterionKind::F1 => ThresholdCriterion::F1,
+            CriterionKind::Youden => ThresholdCriterion::YoudensJ,
+        };
+        let (threshold, score) = best_threshold(
+            &probabilities(&mlp, &calibration_samples)?,
+            &calibration_targets,
+            criterion,
+        );
+        if text {
+            println!(
+                "Best threshold = {} ({:?} = {:.4})",
+                threshold, criterion, score
+            );
+        }
+        mlp.set_threshold(threshold);
+    }
+    if args.calibrate.is_some() || args.tune_threshold.is_some() {
+        mlp.dump(&args.mlp_filename)?;
+        if text {
+            println!("Saved {}", args.mlp_filename);
+        }
+    }
+
+    if text {
+        match mlp.calibration() {
+            Calibration::None => {}
+            Calibration::Platt { a, b } => println!("Platt scaling: a = {}, b = {}", a, b),
+            Calibration::Isotonic { x, .. } => {
+                println!("Isotonic calibration with {} steps", x.len())
+            }
+        }
+    }
+    let threshold = args.threshold.unwrap_or(mlp.threshold());
+    let metrics = Metrics::new(&probabilities(&mlp, &samples)?, &targets, threshold);
+    match args.format {
+        OutputFormat::Text => println!("{}", metrics),
+        OutputFormat::Json => println!("{}", to_json(&metrics)?),
+    }
+
+    Ok(())
+}
+
+// Calibrated probabilities from the first output
+fn probabilities(mlp: &MLP, samples: &[LightSample]) -> Result<Vec<f64>, ImportError> {
+    Ok(predict_samples(mlp, samples)?.column(0).to_vec())
+}
+
+fn to_json(value: &impl Serialize) -> Result<String, std::io::Error> {
+    Ok(serde_json::to_string_pretty(value)?)
+}
+
+#[derive(Serialize)]
+struct FoldMetrics {
+    fold: usize,
+    train_samples: usize,
+    test_start: chrono::NaiveDate,
+    test_end: chrono::NaiveDate,
+    metrics: Metrics,
+}
+
+fn run_walk_forward(args: &EvaluateArgs, mut samples: Vec<LightSample>) -> Result<(), ImportError> {
+    let seed = args.hyper.seed(None);
+    if args.format == OutputFormat::Text {
+        println!("seed = {}", seed);
+    }
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    samples.sort_by_key(|s| s.time);
+    let features = args.hyper.features()?;
+    let folds = walk_forward(
+        &samples,
+        Duration::days(args.fold_days),
+        args.min_train_folds,
+        &features.timezone,
+    );
+    if folds.is_empty() {
+        println!("Not enough data for any walk-forward folds");
+        return Ok(());
+    }
+
+    let (all_inputs, all_targets) = inputs_and_targets(&samples, &features);
+    let mut results: Vec<FoldMetrics> = vec![];
+    for (k, fold) in folds.iter().enumerate() {
+        // randomly order the training data
+        let mut order: Vec<usize> = fold.train.clone().collect();
+        order.shuffle(&mut rng);
+        let inputs = order.iter().map(|&i| all_inputs[i].clone()).collect();
+        let targets = order.iter().map(|&i| all_targets[i].clone()).collect();
+
+        // every fold starts from the same initial weights
+        let mut mlp = MLP::new(args.hyper.config(seed, features.clone())?);
+        mlp.train(inputs, targets, None, args.hyper.epochs);
+        let result = FoldMetrics {
+            fold: k + 1,
+            train_samples: fold.train.len(),
+            test_start: fold
+                .test_start
+                .with_timezone(&features.timezone)
+                .date_naive(),
+            test_end: fold.test_end.with_timezone(&features.timezone).date_naive(),
+            metrics: Metrics::new(
+                &probabilities(&mlp, &samples[fold.test.clone()])?,
+                &all_targets[fold.test.clone()]
+                    .iter()
+                    .map(|y| y[0])
+                    .collect::<Vec<_>>(),
+                args.threshold.unwrap_or(mlp.threshold()),
+            ),
+        };
+        if args.format == OutputFormat::Text {
+            let m = &result.metrics;
+            println!(
+                "Fold {}: train {} samples, test {} to {} ({} samples); log loss = {:.4}, balanced accuracy = {:.4}, F1 = {:.4}, ROC-AUC = {}",
+                result.fold,
+                result.train_samples,
+                result.test_start,
+                result.test_end,
+                m.samples,
+                m.log_loss,
+                m.balanced_accuracy,
+                m.f1,
+                m.roc_auc
+                    .map_or("n/a".to_string(), |auc| format!("{:.4}", auc))
+            );
+        }
+        results.push(result);
+    }
+
+    match args.format {
+        OutputFormat::Text => {
+            let n = results.len() as f64;
+            let mean =
+                |f: fn(&Metrics) -> f64| results.iter().map(|r| f(&r.metrics)).sum::<f64>() / n;
+            println!(
+                "Mean over {} folds; log loss = {:.4}, balanced accuracy = {:.4}, F1 = {:.4}",
+                results.len(),
+                mean(|m| m.log_loss),
+                mean(|m| m.balanced_accuracy),
+                mean(|m| m.f1)
+            );
+        }
+        OutputFormat::Json => println!("{}", to_json(&results)?),
+    }
+
+    Ok(())
+}
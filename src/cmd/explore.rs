@@ -1,3 +1,4 @@
+use chrono::NaiveTime;
 use clap::Args;
 use futures::TryStreamExt;
 use sqlx::postgres::PgPoolOptions;
@@ -28,7 +29,11 @@ pub async fn run(args: &ExploreArgs) -> Result<(), sqlx::Error> {
         .await?;
 
     let mut sql_buf = String::new();
-    let mut results = db::stream_query(&pool, &mut sql_buf, Some(args.from), Some(args.to)).await;
+    let (from, to) = (
+        args.from.and_time(NaiveTime::MIN),
+        args.to.and_time(NaiveTime::MIN),
+    );
+    let mut results = db::stream_query(&pool, &mut sql_buf, Some(from), Some(to)).await;
     let mut row_ctr = 1;
     while let Some(light_data) = results.try_next().await? {
         println!("{}, {:?}", row_ctr, light_data);
@@ -1,3 +1,5 @@
+use chrono::NaiveTime;
+use chrono_tz::Tz;
 use clap::Args;
 use futures::{Stream, TryStreamExt};
 use polars::prelude::*;
@@ -7,9 +9,9 @@ use tokio::pin;
 
 use std::fs;
 
-use super::cli::parse_date;
+use super::cli::{parse_date, parse_timezone};
 use crate::{
-    data::tsg::LightTimeSeriesGenerator,
+    data::{timezone::local_to_utc, tsg::LightTimeSeriesGenerator},
     db::{self, LightEvent, LightState},
 };
 
@@ -29,11 +31,15 @@ pub struct ExportDBArgs {
     /// to date, example: 2022-03-22
     #[arg(short, long, value_parser = parse_date)]
     to: chrono::NaiveDate,
+    /// IANA timezone the from and to dates are in, e.g. Europe/London
+    #[arg(long, env = "HUEML_TIMEZONE", default_value = "UTC", value_parser = parse_timezone)]
+    timezone: Tz,
 }
 
 // https://pola-rs.github.io/polars-book/user-guide/
 
 #[derive(Debug, Error)]
+#[allow(clippy::enum_variant_names)]
 pub enum ExportDBError {
     #[error("I/O error: {0}")]
     IoError(#[from] std::io::Error),
@@ -102,10 +108,10 @@ pub async fn write_parquet(
         println!("{}, {:?}", row_ctr, light_data);
         tsg.event(light_data);
 
-        while let Some(sample) = tsg.next() {
+        for sample in tsg.by_ref() {
             let df = df!(
                 "timestamp" => [sample.time.naive_utc()],
-                "state" => [if sample.state == LightState::On { true } else { false } ]
+                "state" => [sample.state == LightState::On]
             )?;
             bw.write_batch(&df)?;
         }
@@ -123,7 +129,10 @@ pub async fn run(args: &ExportDBArgs) -> Result<(), ExportDBError> {
         .await?;
 
     let mut sql_buf = String::new();
-    let results = db::stream_query(&pool, &mut sql_buf, Some(args.from), Some(args.to)).await;
+    // creationtime is stored in UTC, so the range runs between local midnights
+    let from = local_to_utc(&args.timezone, args.from.and_time(NaiveTime::MIN)).naive_utc();
+    let to = local_to_utc(&args.timezone, args.to.and_time(NaiveTime::MIN)).naive_utc();
+    let results = db::stream_query(&pool, &mut sql_buf, Some(from), Some(to)).await;
 
     let file = fs::File::create(&args.filename)?;
     //write_csv(results, file).await?;
@@ -1,3 +1,4 @@
+use chrono::Utc;
 use clap::Args;
 use itertools::multizip;
 use polars::prelude::*;
@@ -5,7 +6,10 @@ use thiserror::Error;
 
 use std::fs;
 
-use crate::db::{LightEvent, LightState};
+use crate::{
+    data::types::LightSample,
+    db::{LightEvent, LightState},
+};
 
 #[derive(Args)]
 pub struct ImportArgs {
@@ -23,6 +27,12 @@ pub enum ImportError {
     PolarsError(#[from] PolarsError),
     #[error("Insufficient layers: {0}")]
     NotEnoughLayers(String),
+    #[error("Invalid activation: {0}")]
+    InvalidActivation(String),
+    #[error("Invalid features: {0}")]
+    InvalidFeatures(String),
+    #[error("Empty split: {0}")]
+    EmptySplit(String),
 }
 
 // NB: not lazy, polars LazyFrame::scan doesn't seem to play well with async
@@ -66,7 +76,7 @@ pub fn run(args: &ImportArgs) -> Result<(), ImportError> {
     let res: Vec<_> = combined
         .map(|(ts, st)| LightEvent {
             id: String::new(),
-            creationtime: ts.unwrap().into(),
+            creationtime: ts.unwrap(),
             state: if st.unwrap() {
                 LightState::On
             } else {
@@ -87,3 +97,25 @@ pub fn run(args: &ImportArgs) -> Result<(), ImportError> {
     //let h = result.head(Some(3));
     Ok(())
 }
+
+// Reads the (timestamp, state) samples written by export-db, in file order
+pub fn read_light_samples(filename: &str) -> Result<Vec<LightSample>, ImportError> {
+    let mut file = fs::File::open(filename)?;
+    let df = ParquetReader::new(&mut file).finish()?;
+
+    let cols = df.take_columns();
+    let timestamp = cols[0].datetime()?.as_datetime_iter();
+    let state = cols[1].bool()?.iter();
+
+    let combined = multizip((timestamp, state));
+    Ok(combined
+        .map(|(ts, st)| LightSample {
+            time: ts.unwrap().and_local_timezone(Utc).unwrap(),
+            state: if st.unwrap() {
+                LightState::On
+            } else {
+                LightState::Off
+            },
+        })
+        .collect())
+}
@@ -1,7 +1,9 @@
 pub mod cli;
+pub mod evaluate;
 pub mod explore;
 pub mod exportdb;
 pub mod import;
+pub mod plot;
 pub mod predict;
 pub mod train;
 
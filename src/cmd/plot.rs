@@ -0,0 +1,515 @@
+use std::fs::File;
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveTime, Utc};
+use chrono_tz::Tz;
+use clap::{Args, Subcommand};
+use plotters::{coord::Shift, prelude::*};
+use polars::prelude::*;
+use thiserror::Error;
+
+use super::{
+    import::{ImportError, read_light_samples},
+    predict::predict_samples,
+};
+use crate::{
+    data::{timezone::local_to_utc, types::LightSample},
+    db::LightState,
+    mlp::mlp::MLP,
+};
+
+#[derive(Args)]
+pub struct PlotArgs {
+    /// file to write, rendered as PNG for a .png extension and SVG otherwise
+    #[arg(short, long, global = true, default_value = "plot.svg")]
+    output: String,
+    #[arg(long, global = true, default_value_t = 1024)]
+    width: u32,
+    #[arg(long, global = true, default_value_t = 600)]
+    height: u32,
+    #[command(subcommand)]
+    chart: PlotCommands,
+}
+
+#[derive(Subcommand)]
+pub enum PlotCommands {
+    /// loss curves from a history file written by train --history
+    /// example: cargo run --release plot --output loss.svg loss --filename data/history.csv
+    Loss {
+        #[arg(short, long)]
+        filename: String,
+    },
+    /// predicted on-probability by day of week and time of day
+    /// example: cargo run --release plot --output week.png heatmap --year 2024 --month 12
+    Heatmap {
+        #[arg(short, long, default_value = "data/mlp.json")]
+        mlp_filename: String,
+        /// month of the year (1-12) to predict for
+        #[arg(long, value_parser = clap::value_parser!(u32).range(1..=12), default_value_t = Utc::now().month())]
+        month: u32,
+        /// year the week is taken from, which decides its dates and any calendar features
+        #[arg(long, value_parser = clap::value_parser!(i32).range(1..=9999), default_value_t = Utc::now().year())]
+        year: i32,
+    },
+    /// actual state against predicted on-probability for a file written by export-db
+    /// example: cargo run --release plot --output jan.svg timeline --filename data/2024-jan.parquet
+    Timeline {
+        #[arg(short, long)]
+        filename: String,
+        #[arg(short, long, default_value = "data/mlp.json")]
+        mlp_filename: String,
+    },
+}
+
+#[derive(Error, Debug)]
+pub enum PlotError {
+    #[error("{0}")]
+    ImportError(#[from] ImportError),
+    #[error("Polars error: {0}")]
+    PolarsError(#[from] PolarsError),
+    #[error("Drawing error: {0}")]
+    DrawingError(String),
+    #[error("Nothing to plot: {0}")]
+    NoData(String),
+}
+
+fn drawing_error<E: std::error::Error + Send + Sync>(e: DrawingAreaErrorKind<E>) -> PlotError {
+    PlotError::DrawingError(e.to_string())
+}
+
+// Anything that can be drawn on either backend
+trait Chart {
+    fn draw<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>) -> Result<(), PlotError>;
+}
+
+fn render(chart: &impl Chart, output: &str, size: (u32, u32)) -> Result<(), PlotError> {
+    if output.ends_with(".png") {
+        let root = BitMapBackend::new(output, size).into_drawing_area();
+        chart.draw(&root)?;
+        root.present().map_err(drawing_error)
+    } else {
+        let root = SVGBackend::new(output, size).into_drawing_area();
+        chart.draw(&root)?;
+        root.present().map_err(drawing_error)
+    }
+}
+
+pub async fn run(args: &PlotArgs) -> Result<(), PlotError> {
+    let size = (args.width, args.height);
+    match &args.chart {
+        PlotCommands::Loss { filename } => {
+            render(&LossCurves::read(filename)?, &args.output, size)?
+        }
+        PlotCommands::Heatmap {
+            mlp_filename,
+            month,
+            year,
+        } => {
+            let mlp = MLP::load(mlp_filename).map_err(ImportError::from)?;
+            let heatmap = WeekHeatmap::predict(&mlp, *year, *month)?;
+            render(&heatmap, &args.output, size)?
+        }
+        PlotCommands::Timeline {
+            filename,
+            mlp_filename,
+        } => {
+            let samples = read_light_samples(filename)?;
+            let mlp = MLP::load(mlp_filename).map_err(ImportError::from)?;
+            let timeline = Timeline::predict(&mlp, samples)?;
+            render(&timeline, &args.output, size)?
+        }
+    }
+    println!("Wrote {}", args.output);
+    Ok(())
+}
+
+struct LossCurves {
+    epochs: Vec<u32>,
+    loss: Vec<f64>,
+    validation_loss: Vec<Option<f64>>,
+}
+
+impl LossCurves {
+    // Reads parquet for a .parquet extension, otherwise CSV, as written by History::write
+    fn read(filename: &str) -> Result<Self, PlotError> {
+        let mut file = File::open(filename).map_err(ImportError::from)?;
+        let df = if filename.ends_with(".parquet") {
+            ParquetReader::new(&mut file).finish()?
+        } else {
+            CsvReader::new(&mut file).finish()?
+        };
+        let epochs = df.column("epoch")?.cast(&DataType::UInt32)?;
+        let loss = df.column("loss")?.cast(&DataType::Float64)?;
+        let validation_loss = df.column("validation_loss")?.cast(&DataType::Float64)?;
+        let curves = Self {
+            epochs: epochs.u32()?.into_no_null_iter().collect(),
+            loss: loss.f64()?.into_no_null_iter().collect(),
+            validation_loss: validation_loss.f64()?.iter().collect(),
+        };
+        if curves.epochs.is_empty() {
+            return Err(PlotError::NoData(filename.to_string()));
+        }
+        Ok(curves)
+    }
+}
+
+impl Chart for LossCurves {
+    fn draw<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>) -> Result<(), PlotError> {
+        root.fill(&WHITE).map_err(drawing_error)?;
+        let first = *self.epochs.first().unwrap_or(&0);
+        let last = (*self.epochs.last().unwrap_or(&0)).max(first + 1);
+        let max_loss = self
+            .loss
+            .iter()
+            .chain(self.validation_loss.iter().flatten())
+            .fold(0.0, |a: f64, &b| a.max(b));
+
+        let mut chart = ChartBuilder::on(root)
+            .caption("Training loss", ("sans-serif", 24))
+            .margin(10)
+            .x_label_area_size(40)
+            .y_label_area_size(60)
+            .build_cartesian_2d(first..last, 0.0..max_loss * 1.05)
+            .map_err(drawing_error)?;
+        chart
+            .configure_mesh()
+            .x_desc("epoch")
+            .y_desc("loss")
+            .draw()
+            .map_err(drawing_error)?;
+
+        chart
+            .draw_series(LineSeries::new(
+                self.epochs.iter().copied().zip(self.loss.iter().copied()),
+                &BLUE,
+            ))
+            .map_err(drawing_error)?
+            .label("loss")
+            .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], BLUE));
+        if self.validation_loss.iter().any(Option::is_some) {
+            chart
+                .draw_series(LineSeries::new(
+                    self.epochs
+                        .iter()
+                        .zip(&self.validation_loss)
+                        .filter_map(|(&e, v)| v.map(|v| (e, v))),
+                    &RED,
+                ))
+                .map_err(drawing_error)?
+                .label("validation loss")
+                .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], RED));
+        }
+        chart
+            .configure_series_labels()
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .draw()
+            .map_err(drawing_error)
+    }
+}
+
+const SLOTS_PER_DAY: usize = 96;
+const DAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+// Predicted on-probability for every 15 minute slot of a week, indexed [day][slot]
+struct WeekHeatmap {
+    year: i32,
+    month: u32,
+    probabilities: Vec<Vec<f64>>,
+}
+
+impl WeekHeatmap {
+    fn predict(mlp: &MLP, year: i32, month: u32) -> Result<Self, PlotError> {
+        let timezone = mlp.features().map_err(ImportError::from)?.timezone;
+        let samples = week_samples(year, month, &timezone);
+        let outputs = predict_samples(mlp, &samples)?;
+        let probabilities = outputs
+            .column(0)
+            .to_vec()
+            .chunks(SLOTS_PER_DAY)
+            .map(<[f64]>::to_vec)
+            .collect();
+        Ok(Self {
+            year,
+            month,
+            probabilities,
+        })
+    }
+}
+
+// One sample per 15 minute slot of wall-clock time in tz, Monday first, from the first
+// full week of the month in the given year
+fn week_samples(year: i32, month: u32, tz: &Tz) -> Vec<LightSample> {
+    let first = NaiveDate::from_ymd_opt(year, month, 1).unwrap();
+    let days_to_monday = (7 - first.weekday().num_days_from_monday()) % 7;
+    let monday = first + Duration::days(days_to_monday.into());
+    let start = monday.and_time(NaiveTime::MIN);
+    (0..DAYS.len() * SLOTS_PER_DAY)
+        .map(|i| LightSample {
+            time: local_to_utc(tz, start + Duration::minutes(15 * i as i64)),
+            state: LightState::Off,
+        })
+        .collect()
+}
+
+// Dark blue for off through to yellow for on
+fn probability_colour(p: f64) -> RGBColor {
+    let p = p.clamp(0.0, 1.0);
+    let mix = |from: f64, to: f64| (from + (to - from) * p).round() as u8;
+    RGBColor(mix(20.0, 250.0), mix(30.0, 220.0), mix(90.0, 40.0))
+}
+
+impl Chart for WeekHeatmap {
+    fn draw<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>) -> Result<(), PlotError> {
+        root.fill(&WHITE).map_err(drawing_error)?;
+        let month = chrono::Month::try_from(self.month as u8)
+            .map(|m| m.name())
+            .unwrap_or_default();
+        let mut chart = ChartBuilder::on(root)
+            .caption(
+                format!("Predicted on-probability, {} {}", month, self.year),
+                ("sans-serif", 24),
+            )
+            .margin(10)
+            .x_label_area_size(40)
+            .y_label_area_size(50)
+            .build_cartesian_2d(0.0..24.0, 0.0..DAYS.len() as f64)
+            .map_err(drawing_error)?;
+        chart
+            .configure_mesh()
+            .disable_mesh()
+            .x_desc("time of day")
+            .x_labels(13)
+            .x_label_formatter(&|h| format!("{:02}:00", *h as u32))
+            .y_labels(DAYS.len() * 2 + 1)
+            .y_label_formatter(&|d| {
+                // label the middle of each row, Monday at the top
+                let row = *d - 0.5;
+                if row.fract() == 0.0 && row >= 0.0 && (row as usize) < DAYS.len() {
+                    DAYS[DAYS.len() - 1 - row as usize].to_string()
+                } else {
+                    String::new()
+                }
+            })
+            .draw()
+            .map_err(drawing_error)?;
+
+        let slot_hours = 24.0 / SLOTS_PER_DAY as f64;
+        chart
+            .draw_series(
+                self.probabilities
+                    .iter()
+                    .enumerate()
+                    .flat_map(|(day, slots)| {
+                        let top = (DAYS.len() - day) as f64;
+                        slots.iter().enumerate().map(move |(slot, &p)| {
+                            let x = slot as f64 * slot_hours;
+                            Rectangle::new(
+                                [(x, top), (x + slot_hours, top - 1.0)],
+                                probability_colour(p).filled(),
+                            )
+                        })
+                    }),
+            )
+            .map_err(drawing_error)?;
+        Ok(())
+    }
+}
+
+// Actual state and predicted on-probability against time, labelled in the model's timezone
+struct Timeline {
+    timezone: Tz,
+    times: Vec<DateTime<Utc>>,
+    actual: Vec<f64>,
+    predicted: Vec<f64>,
+}
+
+impl Timeline {
+    fn predict(mlp: &MLP, samples: Vec<LightSample>) -> Result<Self, PlotError> {
+        if samples.is_empty() {
+            return Err(PlotError::NoData("no samples".to_string()));
+        }
+        let outputs = predict_samples(mlp, &samples)?;
+        Ok(Self {
+            timezone: mlp.features().map_err(ImportError::from)?.timezone,
+            times: samples.iter().map(|s| s.time).collect(),
+            actual: samples.iter().map(LightSample::on).collect(),
+            predicted: outputs.column(0).to_vec(),
+        })
+    }
+}
+
+impl Chart for Timeline {
+    fn draw<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>) -> Result<(), PlotError> {
+        root.fill(&WHITE).map_err(drawing_error)?;
+        let seconds = |t: &DateTime<Utc>| t.timestamp();
+        let start = seconds(&self.times[0]);
+        let end = seconds(&self.times[self.times.len() - 1]).max(start + 1);
+        let mut chart = ChartBuilder::on(root)
+            .caption("Actual vs predicted", ("sans-serif", 24))
+            .margin(10)
+            .x_label_area_size(40)
+            .y_label_area_size(50)
+            .build_cartesian_2d(start..end, -0.05..1.05)
+            .map_err(drawing_error)?;
+        chart
+            .configure_mesh()
+            .x_labels(8)
+            .x_label_formatter(&|s| {
+                DateTime::from_timestamp(*s, 0)
+                    .map(|t| {
+                        t.with_timezone(&self.timezone)
+                            .format("%Y-%m-%d %H:%M")
+                            .to_string()
+                    })
+                    .unwrap_or_default()
+            })
+            .y_desc("on")
+            .draw()
+            .map_err(drawing_error)?;
+
+        // hold each actual state until the next sample
+        let steps = self
+            .times
+            .iter()
+            .zip(&self.actual)
+            .enumerate()
+            .flat_map(|(i, (t, &on))| {
+                let next = self.times.get(i + 1).map_or(seconds(t), seconds);
+                [(seconds(t), on), (next, on)]
+            });
+        let actual_colour = RGBColor(120, 120, 120);
+        chart
+            .draw_series(
+                AreaSeries::new(steps, 0.0, actual_colour.mix(0.3)).border_style(actual_colour),
+            )
+            .map_err(drawing_error)?
+            .label("actual")
+            .legend(move |(x, y)| {
+                Rectangle::new(
+                    [(x, y - 5), (x + 20, y + 5)],
+                    actual_colour.mix(0.3).filled(),
+                )
+            });
+        chart
+            .draw_series(LineSeries::new(
+                self.times
+                    .iter()
+                    .map(seconds)
+                    .zip(self.predicted.iter().copied()),
+                &BLUE,
+            ))
+            .map_err(drawing_error)?
+            .label("predicted")
+            .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], BLUE));
+        chart
+            .configure_series_labels()
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .draw()
+            .map_err(drawing_error)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{Datelike, Duration, TimeZone, Timelike, Utc, Weekday};
+    use chrono_tz::Tz;
+
+    use super::{
+        Chart, DAYS, LossCurves, SLOTS_PER_DAY, Timeline, WeekHeatmap, probability_colour, render,
+        week_samples,
+    };
+    use crate::{
+        data::{idg::FeatureSpec, types::LightSample},
+        db::LightState,
+        mlp::{config::MLPConfig, mlp::MLP},
+    };
+
+    fn model() -> MLP {
+        let features = FeatureSpec::default();
+        MLP::new(MLPConfig {
+            layers: vec![features.width(), 4, 1],
+            features: Some(features),
+            seed: Some(1),
+            ..Default::default()
+        })
+    }
+
+    // Renders to a temporary SVG and checks something was written
+    fn assert_renders(chart: &impl Chart, name: &str) {
+        let path = std::env::temp_dir().join(format!("hueml-plot-test-{}.svg", name));
+        let path = path.to_str().unwrap();
+        render(chart, path, (640, 480)).unwrap();
+        assert!(std::fs::metadata(path).unwrap().len() > 0);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_loss_curves_render() {
+        let curves = LossCurves {
+            epochs: vec![1, 2, 3],
+            loss: vec![0.7, 0.5, 0.4],
+            validation_loss: vec![Some(0.75), None, Some(0.45)],
+        };
+        assert_renders(&curves, "loss");
+    }
+
+    #[test]
+    fn test_heatmap_renders() {
+        assert_renders(&WeekHeatmap::predict(&model(), 2024, 1).unwrap(), "heatmap");
+    }
+
+    #[test]
+    fn test_timeline_renders() {
+        let start = Utc.with_ymd_and_hms(2024, 3, 4, 0, 0, 0).unwrap();
+        let samples = (0..96)
+            .map(|i| LightSample {
+                time: start + Duration::minutes(15 * i),
+                state: if i > 72 {
+                    LightState::On
+                } else {
+                    LightState::Off
+                },
+            })
+            .collect();
+        let timeline = Timeline::predict(&model(), samples).unwrap();
+        assert_renders(&timeline, "timeline");
+    }
+
+    #[test]
+    fn test_week_samples_cover_one_week_of_the_month_in_order() {
+        for month in 1..=12 {
+            let samples = week_samples(2024, month, &Tz::UTC);
+            assert_eq!(samples.len(), DAYS.len() * SLOTS_PER_DAY);
+            assert!(samples.iter().all(|s| s.time.month() == month));
+            assert!(samples.iter().all(|s| s.time.year() == 2024));
+            assert_eq!(samples[0].time.weekday(), Weekday::Mon);
+            assert_eq!(samples[SLOTS_PER_DAY + 4].time.weekday(), Weekday::Tue);
+            assert_eq!(samples[SLOTS_PER_DAY + 4].time.hour(), 1);
+            assert_eq!(samples.last().unwrap().time.weekday(), Weekday::Sun);
+            assert_eq!(samples.last().unwrap().time.minute(), 45);
+        }
+    }
+
+    #[test]
+    fn test_week_samples_follow_local_time() {
+        // New York is on daylight saving time in July, 4 hours behind UTC
+        let samples = week_samples(2024, 7, &Tz::America__New_York);
+        let local = samples[0].time.with_timezone(&Tz::America__New_York);
+        assert_eq!((local.weekday(), local.hour()), (Weekday::Mon, 0));
+        assert_eq!(samples[0].time.hour(), 4);
+    }
+
+    #[test]
+    fn test_week_samples_start_on_the_first_monday_of_the_given_year() {
+        // March 2024 starts on a Friday and March 2025 on a Saturday
+        assert_eq!(week_samples(2024, 3, &Tz::UTC)[0].time.day(), 4);
+        assert_eq!(week_samples(2025, 3, &Tz::UTC)[0].time.day(), 3);
+    }
+
+    #[test]
+    fn test_probability_colour_is_clamped() {
+        assert_eq!(probability_colour(-1.0), probability_colour(0.0));
+        assert_eq!(probability_colour(2.0), probability_colour(1.0));
+    }
+}
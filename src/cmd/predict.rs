@@ -1,18 +1,11 @@
-use std::fs;
-
-use chrono::{Datelike, Utc};
+use chrono::Datelike;
 use clap::Args;
 use colored::Colorize;
-use itertools::multizip;
-use polars::prelude::*;
+use ndarray::Array2;
 
-use crate::{
-    data::{idg::make_input_data_vector, types::LightSample},
-    db::LightState,
-    mlp::mlp::MLP,
-};
+use crate::{data::types::LightSample, db::LightState, mlp::mlp::MLP};
 
-use super::import::ImportError;
+use super::import::{ImportError, read_light_samples};
 
 #[derive(Args)]
 pub struct PredictArgs {
@@ -22,34 +15,30 @@ pub struct PredictArgs {
     mlp_filename: String,
 }
 
-pub async fn run(args: &PredictArgs) -> Result<(), ImportError> {
-    let mut file = fs::File::open(&args.filename)?;
-    let df = ParquetReader::new(&mut file).finish()?;
+// Calibrated probabilities for every sample in one pass, one row of outputs per sample
+pub fn predict_samples(mlp: &MLP, samples: &[LightSample]) -> Result<Array2<f64>, ImportError> {
+    let features = mlp.features()?;
+    let inputs: Vec<Vec<f64>> = samples.iter().map(|s| features.make(s)).collect();
+    // the width comes from the features so no samples still match the input layer
+    let x = Array2::from_shape_fn((inputs.len(), features.width()), |(r, c)| inputs[r][c]);
+    Ok(mlp.probabilities(&x))
+}
 
-    let cols = df.take_columns();
-    let timestamp = cols[0].datetime()?.as_datetime_iter();
-    let state = cols[1].bool()?.iter();
+pub async fn run(args: &PredictArgs) -> Result<(), ImportError> {
+    let res = read_light_samples(&args.filename)?;
 
-    let combined = multizip((timestamp, state));
-    let res: Vec<_> = combined
-        .map(|(ts, st)| LightSample {
-            time: ts.unwrap().and_local_timezone(Utc).unwrap(),
-            state: if st.unwrap() {
-                LightState::On
-            } else {
-                LightState::Off
-            },
-        })
-        .collect();
+    let mlp = MLP::load(&args.mlp_filename)?;
 
-    let mut mlp = MLP::load(&args.mlp_filename, None)?;
+    let outputs = predict_samples(&mlp, &res)?;
 
     let mut count = 0;
     let mut success_count = 0;
-    for le in res.iter() {
-        let input_vec = make_input_data_vector(le);
-        let output = mlp.feed_forward(input_vec);
-        let prediction = if output[[0, 0]] > 0.5 { "on" } else { "off" };
+    for (le, output) in res.iter().zip(outputs.rows()) {
+        let prediction = if output[0] > mlp.threshold() {
+            "on"
+        } else {
+            "off"
+        };
         count += 1;
         if prediction == "on" && le.state == LightState::On
             || prediction == "off" && le.state == LightState::Off
@@ -77,3 +66,23 @@ pub async fn run(args: &PredictArgs) -> Result<(), ImportError> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::predict_samples;
+    use crate::{
+        data::idg::FeatureSpec,
+        mlp::{config::MLPConfig, mlp::MLP},
+    };
+
+    #[test]
+    fn test_predict_samples_without_samples() {
+        let features = FeatureSpec::default();
+        let mlp = MLP::new(MLPConfig {
+            layers: vec![features.width(), 4, 1],
+            features: Some(features),
+            ..Default::default()
+        });
+        assert_eq!(predict_samples(&mlp, &[]).unwrap().shape(), &[0, 1]);
+    }
+}
@@ -1,92 +1,481 @@
-use std::fs;
+use chrono::NaiveDate;
+use chrono_tz::Tz;
+use clap::{Args, ValueEnum};
+use ndarray_rand::rand::{SeedableRng, rngs::StdRng, seq::SliceRandom};
 
-use chrono::Utc;
-use clap::Args;
-use itertools::multizip;
-use polars::prelude::*;
-
-use super::import::ImportError;
+use super::{
+    cli::{LayerSpec, parse_date, parse_fraction, parse_keep_probability, parse_timezone},
+    import::{ImportError, read_light_samples},
+};
 use crate::{
-    data::{idg::make_input_data_vector, types::LightSample},
-    db::LightState,
+    data::{
+        calendar::{Calendar, Country, read_ics},
+        idg::{Feature, FeatureSpec},
+        solar::Location,
+        split::split_at_date,
+        types::LightSample,
+    },
     mlp::{
-        config::{MLPConfig, TrainingState},
+        config::MLPConfig,
+        fns::{MLPFunc, OutputActivation},
+        history::History,
+        init::Initializer,
+        loss::Loss,
         mlp::MLP,
+        observer::{Checkpoint, Console, EarlyStopping, TrainingObserver},
+        optimizer::Optimizer,
+        schedule::Schedule,
     },
 };
 
+#[derive(Clone, Copy, ValueEnum)]
+pub enum LossKind {
+    Mse,
+    /// binary cross-entropy
+    Bce,
+    /// categorical cross-entropy
+    Cce,
+    Huber,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ScheduleKind {
+    Constant,
+    /// multiply by --gamma every --step-size epochs
+    Step,
+    /// multiply by --decay-rate every epoch
+    Exponential,
+    /// cosine annealing with warm restarts every --restart-period epochs
+    Cosine,
+    /// multiply by --plateau-factor when validation loss stops improving
+    Plateau,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum OptimizerKind {
+    Sgd,
+    Nesterov,
+    Rmsprop,
+    Adam,
+    Adamw,
+}
+
+/// Model architecture and training hyperparameters, shared by train and evaluate --walk-forward
 #[derive(Args)]
-pub struct TrainArgs {
-    #[arg(short, long)]
-    filename: String,
+pub struct HyperParams {
+    /// hidden and output layer sizes with optional activations, e.g. 16:relu,16:relu,1:sigmoid;
+    /// the input layer is sized to fit --features
     #[arg(short, long, value_delimiter = ',')]
-    layers: Vec<usize>,
+    layers: Vec<LayerSpec>,
+    /// input features with optional encodings (linear, cyclic or one-hot), e.g.
+    /// time-of-day:cyclic,day-of-week:one-hot,month
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = "time-of-day,day-of-week,month"
+    )]
+    features: Vec<Feature>,
+    /// IANA timezone whose wall-clock time the features are taken from, e.g. Europe/London
+    #[arg(long, env = "HUEML_TIMEZONE", default_value = "UTC", value_parser = parse_timezone)]
+    timezone: Tz,
+    /// latitude,longitude for the solar features (sun-elevation, minutes-since-sunset,
+    /// minutes-to-sunrise, is-dark, minutes-since-dusk, minutes-to-dawn), e.g. 51.5,-0.13
+    #[arg(long, env = "HUEML_LOCATION", allow_hyphen_values = true)]
+    location: Option<Location>,
+    /// country whose public holidays the calendar features (is-holiday, is-away,
+    /// days-to-holiday) use: gb, us, de or fr
+    #[arg(long, env = "HUEML_COUNTRY")]
+    country: Option<Country>,
+    /// iCalendar (.ics) file of school holidays, vacations and away periods for the
+    /// calendar features
+    #[arg(long, env = "HUEML_CALENDAR")]
+    calendar: Option<String>,
+    /// activation for hidden layers without one in --layers
+    #[arg(long, default_value_t = MLPFunc::Tanh)]
+    activation: MLPFunc,
     #[arg(long, default_value_t = 100)]
-    epochs: u32,
+    pub epochs: u32,
     #[arg(long, default_value_t = 0.2)]
     learning_rate: f64,
+    #[arg(long, value_enum, default_value_t = ScheduleKind::Constant)]
+    schedule: ScheduleKind,
+    /// epochs between step decays
+    #[arg(long, default_value_t = 100)]
+    step_size: u32,
+    /// step decay factor
+    #[arg(long, default_value_t = 0.5)]
+    gamma: f64,
+    /// exponential decay factor per epoch
+    #[arg(long, default_value_t = 0.99)]
+    decay_rate: f64,
+    /// epochs in the first cosine cycle
+    #[arg(long, default_value_t = 100)]
+    restart_period: u32,
+    /// growth factor of each subsequent cosine cycle
+    #[arg(long, default_value_t = 1)]
+    restart_multiplier: u32,
+    /// reduce-on-plateau factor
+    #[arg(long, default_value_t = 0.5)]
+    plateau_factor: f64,
+    /// reporting intervals (1% of epochs) without improvement before reducing the rate
+    #[arg(long, default_value_t = 5)]
+    plateau_patience: u32,
+    /// lower bound for the cosine and plateau schedules
+    #[arg(long, default_value_t = 0.0)]
+    min_learning_rate: f64,
+    /// epochs of linear warmup before the schedule starts
+    #[arg(long, default_value_t = 0)]
+    warmup_epochs: u32,
+    /// number of samples per weight update (1 = per-sample SGD)
+    #[arg(long, default_value_t = 1)]
+    batch_size: usize,
+    #[arg(long, value_enum, default_value_t = OptimizerKind::Sgd)]
+    optimizer: OptimizerKind,
+    /// momentum coefficient for nesterov
+    #[arg(long, default_value_t = 0.9)]
+    momentum: f64,
+    /// moving average decay for rmsprop
+    #[arg(long, default_value_t = 0.9)]
+    rho: f64,
+    /// first moment decay for adam/adamw
+    #[arg(long, default_value_t = 0.9)]
+    beta1: f64,
+    /// second moment decay for adam/adamw
+    #[arg(long, default_value_t = 0.999)]
+    beta2: f64,
+    #[arg(long, default_value_t = 1e-8)]
+    epsilon: f64,
+    /// decoupled weight decay for adamw
+    #[arg(long, default_value_t = 0.01)]
+    weight_decay: f64,
+    /// activation of the output layer without one in --layers: identity, sigmoid, softmax or tanh
+    #[arg(long, default_value_t = OutputActivation::Sigmoid)]
+    output_activation: OutputActivation,
+    #[arg(long, value_enum, default_value_t = LossKind::Bce)]
+    loss: LossKind,
+    /// threshold between the quadratic and linear regions of the huber loss
+    #[arg(long, default_value_t = 1.0)]
+    huber_delta: f64,
+    /// weight initialisation: uniform, xavier_uniform, xavier_normal, he_uniform, he_normal,
+    /// lecun_uniform, lecun_normal or orthogonal
+    #[arg(long, default_value_t = Initializer::default())]
+    initializer: Initializer,
+    /// initialise biases to zero
+    #[arg(long)]
+    zero_biases: bool,
+    /// L1 penalty coefficient on the weights
+    #[arg(long, default_value_t = 0.0)]
+    l1: f64,
+    /// L2 penalty coefficient on the weights
+    #[arg(long, default_value_t = 0.0)]
+    l2: f64,
+    /// dropout keep probability per hidden layer, e.g. 0.8,0.5 (the last is repeated)
+    #[arg(long, value_delimiter = ',', value_parser = parse_keep_probability)]
+    keep_probability: Vec<f64>,
+    /// seed for weight initialisation, shuffling and splitting; the resumed model's seed or
+    /// random (and printed) if not given
+    #[arg(long)]
+    seed: Option<u64>,
+}
+
+#[derive(Args)]
+pub struct TrainArgs {
+    #[arg(short, long)]
+    filename: String,
+    #[command(flatten)]
+    hyper: HyperParams,
     #[arg(short, long, default_value = "data/mlp.json")]
     mlp_filename: String,
+    /// continue training a saved model, keeping its layers, hyperparameters, optimizer state
+    /// and epoch count; model options given on the command line are ignored
+    #[arg(long, value_name = "MLP_FILENAME")]
+    resume: Option<String>,
+    /// write the model to --mlp-filename every N epochs during training
+    #[arg(long, value_name = "N")]
+    checkpoint_every: Option<u32>,
+    /// fraction of samples, chosen at random, held out for validation
+    #[arg(long, group = "validation", value_parser = parse_fraction)]
+    validation_fraction: Option<f64>,
+    /// samples from local midnight (in the model's timezone) on this date are held out for
+    /// validation, example: 2023-03-25
+    #[arg(long, group = "validation", value_parser = parse_date)]
+    validation_from: Option<NaiveDate>,
+    /// parquet file of validation samples from export-db
+    #[arg(long, group = "validation")]
+    validation_file: Option<String>,
+    /// stop after this many reporting intervals (1% of epochs) without validation improvement
+    #[arg(long, requires = "validation")]
+    patience: Option<u32>,
+    /// minimum decrease in validation loss that counts as an improvement
+    #[arg(long, default_value_t = 0.0)]
+    min_delta: f64,
+    /// write the learning rate, loss, accuracy, validation metrics and elapsed time of every
+    /// epoch to a CSV file as training runs, or to parquet when training ends if the name
+    /// ends in .parquet
+    #[arg(long)]
+    history: Option<String>,
 }
 
-pub async fn run(args: &TrainArgs) -> Result<(), ImportError> {
-    if args.layers.len() < 2 {
-        return Err(ImportError::NotEnoughLayers(String::from(
-            "At least 2 layers must be defined",
-        )));
+impl HyperParams {
+    fn optimizer(&self) -> Optimizer {
+        match self.optimizer {
+            OptimizerKind::Sgd => Optimizer::SGD,
+            OptimizerKind::Nesterov => Optimizer::Nesterov {
+                momentum: self.momentum,
+            },
+            OptimizerKind::Rmsprop => Optimizer::RMSProp {
+                rho: self.rho,
+                epsilon: self.epsilon,
+            },
+            OptimizerKind::Adam => Optimizer::Adam {
+                beta1: self.beta1,
+                beta2: self.beta2,
+                epsilon: self.epsilon,
+            },
+            OptimizerKind::Adamw => Optimizer::AdamW {
+                beta1: self.beta1,
+                beta2: self.beta2,
+                epsilon: self.epsilon,
+                weight_decay: self.weight_decay,
+            },
+        }
     }
 
-    let mut file = fs::File::open(&args.filename)?;
-    let df = ParquetReader::new(&mut file).finish()?;
-
-    // randomly sample the data
-    let df = df.sample_frac(
-        &Series::new(PlSmallStr::from_static("frac"), &[1]),
-        false,
-        true,
-        None,
-    )?;
-
-    let mut targets = Vec::new();
-    let mut inputs = Vec::new();
-
-    let cols = df.take_columns();
-    let timestamp = cols[0].datetime()?.as_datetime_iter();
-    let state = cols[1].bool()?.iter();
-
-    let combined = multizip((timestamp, state));
-    let res: Vec<_> = combined
-        .map(|(ts, st)| LightSample {
-            time: ts.unwrap().and_local_timezone(Utc).unwrap(),
-            state: if st.unwrap() {
-                LightState::On
-            } else {
-                LightState::Off
+    // Hidden layer activations and the output activation from --layers, falling back to
+    // --activation and --output-activation
+    fn activations(&self) -> Result<(Vec<MLPFunc>, OutputActivation), ImportError> {
+        let hidden = &self.layers[..self.layers.len() - 1];
+        let activations = hidden
+            .iter()
+            .map(|spec| match &spec.activation {
+                Some(name) => name.parse().map_err(ImportError::InvalidActivation),
+                None => Ok(self.activation),
+            })
+            .collect::<Result<_, _>>()?;
+        let output = match &self.layers[self.layers.len() - 1].activation {
+            Some(name) => name.parse().map_err(ImportError::InvalidActivation)?,
+            None => self.output_activation,
+        };
+        Ok((activations, output))
+    }
+
+    fn schedule(&self) -> Schedule {
+        match self.schedule {
+            ScheduleKind::Constant => Schedule::Constant,
+            ScheduleKind::Step => Schedule::Step {
+                step_size: self.step_size,
+                gamma: self.gamma,
+            },
+            ScheduleKind::Exponential => Schedule::Exponential {
+                gamma: self.decay_rate,
+            },
+            ScheduleKind::Cosine => Schedule::CosineWarmRestarts {
+                period: self.restart_period,
+                multiplier: self.restart_multiplier,
+                min_lr: self.min_learning_rate,
+            },
+            ScheduleKind::Plateau => Schedule::ReduceOnPlateau {
+                factor: self.plateau_factor,
+                patience: self.plateau_patience,
+                min_lr: self.min_learning_rate,
+            },
+        }
+    }
+
+    fn loss(&self) -> Loss {
+        match self.loss {
+            LossKind::Mse => Loss::MSE,
+            LossKind::Bce => Loss::BinaryCrossEntropy,
+            LossKind::Cce => Loss::CategoricalCrossEntropy,
+            LossKind::Huber => Loss::Huber {
+                delta: self.huber_delta,
             },
-        })
-        .collect();
+        }
+    }
+
+    // --seed, or the seed stored in a resumed model, or a random seed; callers print it
+    // so the run can be repeated
+    pub fn seed(&self, stored: Option<u64>) -> u64 {
+        self.seed
+            .or(stored)
+            .unwrap_or_else(ndarray_rand::rand::random::<u64>)
+    }
+
+    pub fn features(&self) -> Result<FeatureSpec, ImportError> {
+        let calendar = if self.country.is_some() || self.calendar.is_some() {
+            Some(Calendar {
+                country: self.country,
+                events: match &self.calendar {
+                    Some(filename) => read_ics(filename)?,
+                    None => vec![],
+                },
+            })
+        } else {
+            None
+        };
+        let features = FeatureSpec {
+            features: self.features.clone(),
+            timezone: self.timezone,
+            location: self.location,
+            calendar,
+        };
+        features.validate().map_err(ImportError::InvalidFeatures)?;
+        Ok(features)
+    }
+
+    // The model configuration, taking features from HyperParams::features so callers
+    // building several models read the calendar once
+    pub fn config(&self, seed: u64, features: FeatureSpec) -> Result<MLPConfig, ImportError> {
+        if self.layers.is_empty() {
+            return Err(ImportError::NotEnoughLayers(String::from(
+                "At least the output layer must be defined",
+            )));
+        }
 
-    for le in res.iter() {
-        let input_vec = make_input_data_vector(le);
-        inputs.push(input_vec);
-        targets.push(vec![le.on()]);
+        let (activations, output_activation) = self.activations()?;
+        let config = MLPConfig {
+            layers: std::iter::once(features.width())
+                .chain(self.layers.iter().map(|spec| spec.size))
+                .collect(),
+            activations,
+            output_activation,
+            learning_rate: self.learning_rate,
+            batch_size: self.batch_size,
+            schedule: self.schedule(),
+            warmup_epochs: self.warmup_epochs,
+            optimizer: self.optimizer(),
+            loss: self.loss(),
+            seed: Some(seed),
+            initializer: self.initializer,
+            zero_biases: self.zero_biases,
+            l1: self.l1,
+            l2: self.l2,
+            keep_probabilities: self.keep_probability.clone(),
+            features: Some(features),
+        };
+        config
+            .check_activations()
+            .map_err(ImportError::InvalidActivation)?;
+        Ok(config)
     }
+}
+
+pub async fn run(args: &TrainArgs) -> Result<(), ImportError> {
+    let mut mlp = match &args.resume {
+        Some(path) => {
+            let mlp = MLP::load(path)?;
+            println!("Resuming {} after {} epochs", path, mlp.epochs_trained());
+            mlp
+        }
+        None => MLP::new(
+            args.hyper
+                .config(args.hyper.seed(None), args.hyper.features()?)?,
+        ),
+    };
+    // a resumed run shuffles and splits the same way as the original unless --seed is given
+    let seed = args.hyper.seed(mlp.seed());
+    println!("seed = {}", seed);
+
+    // a resumed model keeps the features it was trained on
+    let features = mlp.features()?.clone();
+    let mut rng = StdRng::seed_from_u64(seed);
+    let samples = read_light_samples(&args.filename)?;
+    let (mut train, validation) = match (
+        args.validation_fraction,
+        args.validation_from,
+        &args.validation_file,
+    ) {
+        (Some(fraction), _, _) => {
+            let mut samples = samples;
+            samples.shuffle(&mut rng);
+            let n_validation = (samples.len() as f64 * fraction).round() as usize;
+            let train = samples.split_off(n_validation);
+            (train, samples)
+        }
+        (_, Some(date), _) => split_at_date(samples, date, &features.timezone),
+        (_, _, Some(filename)) => (samples, read_light_samples(filename)?),
+        _ => (samples, vec![]),
+    };
+    if (args.validation_fraction.is_some() || args.validation_from.is_some())
+        && (train.is_empty() || validation.is_empty())
+    {
+        return Err(ImportError::EmptySplit(format!(
+            "{} training and {} validation samples",
+            train.len(),
+            validation.len()
+        )));
+    }
+    println!(
+        "{} training samples, {} validation samples",
+        train.len(),
+        validation.len()
+    );
+
+    // randomly order the training data
+    train.shuffle(&mut rng);
+    let (inputs, targets) = inputs_and_targets(&train, &features);
+    let (validation_inputs, validation_targets) = inputs_and_targets(&validation, &features);
 
-    let mut mlp = MLP::new(MLPConfig {
-        layers: args.layers.clone(),
-        activation: crate::mlp::fns::TANH,
-        learning_rate: args.learning_rate,
-        training_state_updated: Some(|ts: TrainingState| {
-            println!(
-                "Epoch {} of {}; mse = {}",
-                ts.epoch, ts.total_epochs, ts.mse
-            );
-        }),
+    let validation = if validation.is_empty() {
+        None
+    } else {
+        Some((validation_inputs.as_slice(), validation_targets.as_slice()))
+    };
+    let mut console = Console {
+        every: (args.hyper.epochs / 100).max(1),
+    };
+    let mut early_stopping = args
+        .patience
+        .map(|patience| EarlyStopping::new(patience, args.min_delta));
+    let mut checkpoint = args.checkpoint_every.map(|every| Checkpoint {
+        every,
+        path: args.mlp_filename.clone(),
     });
+    let mut history = match &args.history {
+        Some(path) => History::recording_to(path)?,
+        None => History::default(),
+    };
+    let mut observers: Vec<&mut dyn TrainingObserver> = vec![&mut console, &mut history];
+    if let Some(early_stopping) = early_stopping.as_mut() {
+        observers.push(early_stopping);
+    }
+    if let Some(checkpoint) = checkpoint.as_mut() {
+        observers.push(checkpoint);
+    }
 
-    mlp.train(inputs, targets, args.epochs);
+    let epochs_run = mlp.train_with_observers(
+        inputs,
+        targets,
+        validation,
+        args.hyper.epochs,
+        &mut observers,
+    );
+    if epochs_run < args.hyper.epochs {
+        println!("Stopped early after {} epochs", epochs_run);
+    }
     println!("Training complete!");
+    if let Some(summary) = mlp.history().last() {
+        println!(
+            "{} epochs in {:.1}s; final loss = {}",
+            summary.epochs, summary.elapsed_seconds, summary.final_loss
+        );
+        if let (Some(loss), Some(epoch)) =
+            (summary.best_validation_loss, summary.best_validation_epoch)
+        {
+            println!("Best validation loss = {} at epoch {}", loss, epoch);
+        }
+    }
     mlp.dump(&args.mlp_filename)?;
 
     Ok(())
 }
+
+pub fn inputs_and_targets(
+    samples: &[LightSample],
+    features: &FeatureSpec,
+) -> (Vec<Vec<f64>>, Vec<Vec<f64>>) {
+    samples
+        .iter()
+        .map(|le| (features.make(le), vec![le.on()]))
+        .unzip()
+}
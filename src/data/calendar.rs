@@ -0,0 +1,449 @@
+use std::{fmt, fs, str::FromStr};
+
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+use serde::{Deserialize, Serialize};
+
+// Countries with built-in public holiday rules. Holidays announced as one-offs (jubilees,
+// state funerals) aren't included; add them to an .ics calendar instead
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Country {
+    // England and Wales bank holidays
+    GB,
+    // US federal holidays
+    US,
+    // German nationwide holidays
+    DE,
+    // French public holidays
+    FR,
+}
+
+impl FromStr for Country {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "gb" => Ok(Country::GB),
+            "us" => Ok(Country::US),
+            "de" => Ok(Country::DE),
+            "fr" => Ok(Country::FR),
+            other => Err(format!(
+                "Unknown country {}, expected gb, us, de or fr",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Country {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let code = match self {
+            Country::GB => "gb",
+            Country::US => "us",
+            Country::DE => "de",
+            Country::FR => "fr",
+        };
+        write!(f, "{}", code)
+    }
+}
+
+// How a holiday's date is found in a given year
+enum Rule {
+    Fixed(u32, u32),
+    // days after Easter Sunday
+    Easter(i64),
+    // the nth weekday of a month, counting from the end if n is negative
+    NthWeekday(u32, Weekday, i32),
+}
+
+// What happens to a holiday that falls on a weekend
+enum Observance {
+    // nothing, it's lost
+    Actual,
+    // moved to the next weekday that isn't already a holiday
+    NextWeekday,
+    // Saturday holidays move to Friday and Sunday ones to Monday
+    NearestWeekday,
+}
+
+// (name, rule, first year observed)
+type Holiday = (&'static str, Rule, i32);
+
+impl Country {
+    fn holidays(&self) -> (Vec<Holiday>, Observance) {
+        use Rule::*;
+        match self {
+            Country::GB => (
+                vec![
+                    ("New Year's Day", Fixed(1, 1), 1974),
+                    ("Good Friday", Easter(-2), 1871),
+                    ("Easter Monday", Easter(1), 1871),
+                    (
+                        "Early May bank holiday",
+                        NthWeekday(5, Weekday::Mon, 1),
+                        1978,
+                    ),
+                    ("Spring bank holiday", NthWeekday(5, Weekday::Mon, -1), 1971),
+                    ("Summer bank holiday", NthWeekday(8, Weekday::Mon, -1), 1971),
+                    ("Christmas Day", Fixed(12, 25), 1871),
+                    ("Boxing Day", Fixed(12, 26), 1871),
+                ],
+                Observance::NextWeekday,
+            ),
+            Country::US => (
+                vec![
+                    ("New Year's Day", Fixed(1, 1), 1870),
+                    (
+                        "Martin Luther King Jr. Day",
+                        NthWeekday(1, Weekday::Mon, 3),
+                        1986,
+                    ),
+                    (
+                        "Washington's Birthday",
+                        NthWeekday(2, Weekday::Mon, 3),
+                        1971,
+                    ),
+                    ("Memorial Day", NthWeekday(5, Weekday::Mon, -1), 1971),
+                    ("Juneteenth", Fixed(6, 19), 2021),
+                    ("Independence Day", Fixed(7, 4), 1870),
+                    ("Labor Day", NthWeekday(9, Weekday::Mon, 1), 1894),
+                    ("Columbus Day", NthWeekday(10, Weekday::Mon, 2), 1971),
+                    ("Veterans Day", Fixed(11, 11), 1978),
+                    ("Thanksgiving Day", NthWeekday(11, Weekday::Thu, 4), 1942),
+                    ("Christmas Day", Fixed(12, 25), 1870),
+                ],
+                Observance::NearestWeekday,
+            ),
+            Country::DE => (
+                vec![
+                    ("Neujahr", Fixed(1, 1), 1990),
+                    ("Karfreitag", Easter(-2), 1990),
+                    ("Ostermontag", Easter(1), 1990),
+                    ("Tag der Arbeit", Fixed(5, 1), 1990),
+                    ("Christi Himmelfahrt", Easter(39), 1990),
+                    ("Pfingstmontag", Easter(50), 1990),
+                    ("Tag der Deutschen Einheit", Fixed(10, 3), 1990),
+                    ("1. Weihnachtstag", Fixed(12, 25), 1990),
+                    ("2. Weihnachtstag", Fixed(12, 26), 1990),
+                ],
+                Observance::Actual,
+            ),
+            Country::FR => (
+                vec![
+                    ("Jour de l'an", Fixed(1, 1), 1810),
+                    ("Lundi de Pâques", Easter(1), 1886),
+                    ("Fête du Travail", Fixed(5, 1), 1947),
+                    ("Victoire 1945", Fixed(5, 8), 1982),
+                    ("Ascension", Easter(39), 1802),
+                    ("Lundi de Pentecôte", Easter(50), 1886),
+                    ("Fête nationale", Fixed(7, 14), 1880),
+                    ("Assomption", Fixed(8, 15), 1802),
+                    ("Toussaint", Fixed(11, 1), 1802),
+                    ("Armistice", Fixed(11, 11), 1922),
+                    ("Noël", Fixed(12, 25), 1802),
+                ],
+                Observance::Actual,
+            ),
+        }
+    }
+
+    // The days off for public holidays in a year, in date order
+    pub fn public_holidays(&self, year: i32) -> Vec<(NaiveDate, &'static str)> {
+        let (rules, observance) = self.holidays();
+        let actual: Vec<(NaiveDate, &'static str)> = rules
+            .into_iter()
+            .filter(|(_, _, since)| year >= *since)
+            .filter_map(|(name, rule, _)| Some((rule.date(year)?, name)))
+            .collect();
+
+        let is_weekend = |d: &NaiveDate| d.weekday().num_days_from_monday() >= 5;
+        let mut observed: Vec<(NaiveDate, &'static str)> = match observance {
+            Observance::Actual => actual,
+            Observance::NearestWeekday => actual
+                .into_iter()
+                .map(|(d, name)| match d.weekday() {
+                    Weekday::Sat => (d - Duration::days(1), name),
+                    Weekday::Sun => (d + Duration::days(1), name),
+                    _ => (d, name),
+                })
+                .collect(),
+            Observance::NextWeekday => {
+                // weekday holidays keep their dates, then weekend ones take the next free day
+                let (weekend, mut days): (Vec<_>, Vec<_>) =
+                    actual.into_iter().partition(|(d, _)| is_weekend(d));
+                for (d, name) in weekend {
+                    let mut day = d;
+                    while is_weekend(&day) || days.iter().any(|(taken, _)| *taken == day) {
+                        day += Duration::days(1);
+                    }
+                    days.push((day, name));
+                }
+                days
+            }
+        };
+        observed.sort();
+        observed
+    }
+}
+
+impl Rule {
+    fn date(&self, year: i32) -> Option<NaiveDate> {
+        match *self {
+            Rule::Fixed(month, day) => NaiveDate::from_ymd_opt(year, month, day),
+            Rule::Easter(days) => Some(easter(year) + Duration::days(days)),
+            Rule::NthWeekday(month, weekday, n) if n < 0 => {
+                let next_month = if month == 12 {
+                    NaiveDate::from_ymd_opt(year + 1, 1, 1)
+                } else {
+                    NaiveDate::from_ymd_opt(year, month + 1, 1)
+                }?;
+                let last = next_month - Duration::days(1);
+                let back = (7 + last.weekday().num_days_from_monday()
+                    - weekday.num_days_from_monday())
+                    % 7;
+                Some(last - Duration::days((back + 7 * (-n - 1) as u32).into()))
+            }
+            Rule::NthWeekday(month, weekday, n) => {
+                NaiveDate::from_weekday_of_month_opt(year, month, weekday, n as u8)
+            }
+        }
+    }
+}
+
+// Easter Sunday in the Gregorian calendar (the anonymous algorithm in Meeus)
+pub fn easter(year: i32) -> NaiveDate {
+    let a = year % 19;
+    let b = year / 100;
+    let c = year % 100;
+    let d = b / 4;
+    let e = b % 4;
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let i = c / 4;
+    let k = c % 4;
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let month = (h + l - 7 * m + 114) / 31;
+    let day = (h + l - 7 * m + 114) % 31 + 1;
+    NaiveDate::from_ymd_opt(year, month as u32, day as u32).unwrap()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    // School holidays, vacations and other days off
+    Holiday,
+    // Nobody home
+    Away,
+}
+
+// A whole-day calendar entry from start up to but not including end
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Event {
+    pub kind: EventKind,
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub summary: String,
+}
+
+impl Event {
+    fn contains(&self, date: NaiveDate) -> bool {
+        self.start <= date && date < self.end
+    }
+}
+
+// Public holidays for a country plus events from a user's calendar. The events are stored
+// with the model, so it doesn't need the .ics file to make predictions
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Calendar {
+    pub country: Option<Country>,
+    pub events: Vec<Event>,
+}
+
+impl Calendar {
+    pub fn is_holiday(&self, date: NaiveDate) -> bool {
+        // a US New Year's Day on a Saturday is observed on the last day of the year before
+        self.country.is_some_and(|c| {
+            [date.year(), date.year() + 1]
+                .into_iter()
+                .flat_map(|year| c.public_holidays(year))
+                .any(|(d, _)| d == date)
+        }) || self
+            .events
+            .iter()
+            .any(|e| e.kind == EventKind::Holiday && e.contains(date))
+    }
+
+    pub fn is_away(&self, date: NaiveDate) -> bool {
+        self.events
+            .iter()
+            .any(|e| e.kind == EventKind::Away && e.contains(date))
+    }
+
+    // Days until the next holiday, 0 on a holiday. None if there's none within a year
+    pub fn days_to_holiday(&self, date: NaiveDate) -> Option<i64> {
+        let public = self.country.iter().flat_map(|c| {
+            [date.year(), date.year() + 1]
+                .into_iter()
+                .flat_map(|year| c.public_holidays(year))
+                .map(|(d, _)| d)
+        });
+        let events = self
+            .events
+            .iter()
+            .filter(|e| e.kind == EventKind::Holiday && e.end > date)
+            .map(|e| e.start.max(date));
+        public
+            .chain(events)
+            .filter(|d| *d >= date)
+            .map(|d| (d - date).num_days())
+            .filter(|&days| days <= 366)
+            .min()
+    }
+}
+
+impl fmt::Display for Calendar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.country {
+            Some(country) => write!(f, "{} holidays", country)?,
+            None => write!(f, "no public holidays")?,
+        }
+        write!(f, " and {} calendar events", self.events.len())
+    }
+}
+
+// Reads the events of an iCalendar file, see parse_ics
+pub fn read_ics(path: &str) -> std::io::Result<Vec<Event>> {
+    let text = fs::read_to_string(path)?;
+    parse_ics(&text).map_err(|message| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("{}: {}", path, message),
+        )
+    })
+}
+
+// The holidays and away periods in iCalendar (RFC 5545) text. A VEVENT with an AWAY
+// category or the word "away" in its SUMMARY is an away period; one with a HOLIDAY or
+// VACATION category, or a summary mentioning a holiday, vacation, half term or break, is
+// a holiday; anything else, like the term dates in a school calendar, is skipped. Only
+// the date part of DTSTART and DTEND is used, as written. Recurring holidays and away
+// periods (RRULE) are an error rather than being reduced to their first occurrence
+pub fn parse_ics(text: &str) -> Result<Vec<Event>, String> {
+    // long lines are folded onto continuation lines starting with a space or tab
+    let mut lines: Vec<String> = vec![];
+    for line in text.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(line.to_string()),
+        }
+    }
+
+    let mut events = vec![];
+    let mut current: Option<Vec<(String, String)>> = None;
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        // drop parameters such as ;VALUE=DATE or ;TZID=Europe/London
+        let name = name.split(';').next().unwrap_or_default().to_uppercase();
+        match (name.as_str(), value.trim()) {
+            ("BEGIN", "VEVENT") => current = Some(vec![]),
+            ("END", "VEVENT") => {
+                let properties = current
+                    .take()
+                    .ok_or_else(|| "END:VEVENT without BEGIN:VEVENT".to_string())?;
+                if let Some(event) = make_event(&properties)? {
+                    events.push(event);
+                }
+            }
+            (_, value) => {
+                if let Some(properties) = current.as_mut() {
+                    properties.push((name.clone(), value.to_string()));
+                }
+            }
+        }
+    }
+    if current.is_some() {
+        return Err("BEGIN:VEVENT without END:VEVENT".to_string());
+    }
+    events.sort_by_key(|e| e.start);
+    Ok(events)
+}
+
+fn make_event(properties: &[(String, String)]) -> Result<Option<Event>, String> {
+    let property = |name: &str| {
+        properties
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    };
+    let summary = unescape(property("SUMMARY").unwrap_or_default());
+    // CATEGORIES may be repeated, each a comma separated list
+    let categories: Vec<String> = properties
+        .iter()
+        .filter(|(n, _)| n == "CATEGORIES")
+        .flat_map(|(_, v)| v.split(','))
+        .map(|c| unescape(c.trim()).to_lowercase())
+        .collect();
+    let category = |names: &[&str]| categories.iter().any(|c| names.contains(&c.as_str()));
+    // whole words, so "Breakfast club" isn't a break and "Giveaway" isn't away
+    let words: Vec<String> = summary
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_string)
+        .collect();
+    let word = |names: &[&str]| words.iter().any(|w| names.contains(&w.as_str()));
+    let half_term = words.windows(2).any(|w| w[0] == "half" && w[1] == "term");
+    let kind = if category(&["away"]) || word(&["away"]) {
+        EventKind::Away
+    } else if category(&["holiday", "holidays", "vacation"])
+        || word(&["holiday", "holidays", "vacation", "vacations", "break"])
+        || half_term
+    {
+        EventKind::Holiday
+    } else {
+        return Ok(None);
+    };
+    if property("RRULE").is_some() {
+        return Err(format!(
+            "{} repeats, which isn't supported; add each occurrence as its own event",
+            summary
+        ));
+    }
+
+    let start_value = property("DTSTART").ok_or(format!("{} has no DTSTART", summary))?;
+    let start = parse_ics_date(start_value)?;
+    let end = match property("DTEND") {
+        // a date is already exclusive; a time later than midnight ends during that day
+        Some(v) if v.get(9..).is_some_and(|t| !t.starts_with("000000")) => {
+            parse_ics_date(v)? + Duration::days(1)
+        }
+        Some(v) => parse_ics_date(v)?,
+        None => start + Duration::days(1),
+    };
+    Ok(Some(Event {
+        kind,
+        start,
+        end: end.max(start + Duration::days(1)),
+        summary,
+    }))
+}
+
+// The date of a DATE (20240101) or DATE-TIME (20240101T090000Z) value
+fn parse_ics_date(value: &str) -> Result<NaiveDate, String> {
+    value
+        .get(..8)
+        .and_then(|d| NaiveDate::parse_from_str(d, "%Y%m%d").ok())
+        .ok_or(format!("Invalid date {}", value))
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("\\n", " ")
+        .replace("\\N", " ")
+        .replace("\\,", ",")
+        .replace("\\;", ";")
+        .replace("\\\\", "\\")
+}
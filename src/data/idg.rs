@@ -1,17 +1,409 @@
-use chrono::{Datelike, Timelike};
-
-use super::types::LightSample;
-
-pub fn make_input_data_vector(sample: &LightSample) -> Vec<f64> {
-    // Our independent variables are:
-    // time of day (minutes since 00:00, normalised to 1)
-    let mins = (sample.time.minute() + 60 * sample.time.hour()) as f64 / 1440.0;
-    // day of week
-    let day_of_week = (sample.time.weekday().num_days_from_monday()) as f64 / 6.0;
-    // month
-    let month = (sample.time.month0()) as f64 / 11.0;
-    // day of year
-    //let day_of_year = sample.time.ordinal() as f64 / 365.0;
-
-    vec![mins, day_of_week, month]
+use std::{f64::consts::TAU, fmt, str::FromStr};
+
+use chrono::{DateTime, Datelike, NaiveDate, Timelike, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    calendar::Calendar,
+    solar::{self, CIVIL_TWILIGHT_ELEVATION, Location, SUNRISE_ELEVATION, SunEvent},
+    types::LightSample,
+};
+
+// How a periodic value is presented to the network
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Encoding {
+    // Scaled to [0, 1], so the end of the period is as far as possible from its start
+    #[default]
+    Linear,
+    // sin and cos of the angle around the period: two inputs, continuous across the
+    // wrap from 23:55 to 00:00, Sunday to Monday or December to January
+    Cyclic,
+    // One input per category (hour, weekday, month or day), 1 for the current one
+    OneHot,
+}
+
+// A single input feature. The order of features in a FeatureSpec is the order of the
+// network inputs
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Feature {
+    // Minutes since 00:00; one-hot by hour
+    TimeOfDay { encoding: Encoding },
+    DayOfWeek { encoding: Encoding },
+    Month { encoding: Encoding },
+    DayOfYear { encoding: Encoding },
+    // The solar features need a location and are always linear.
+    // Sun elevation in degrees over 90, so -1 to 1
+    SunElevation,
+    // Minutes since the last sunset or until the next sunrise over a day, capped at 1
+    // and 0 while the sun is up
+    MinutesSinceSunset,
+    MinutesToSunrise,
+    // 1 between the end of civil twilight in the evening (dusk) and its start in the
+    // morning (dawn)
+    IsDark,
+    // Minutes since dusk or until dawn over a day, capped at 1 and 0 while it's light
+    MinutesSinceDusk,
+    MinutesToDawn,
+    // The calendar features need a calendar and are always linear, taken from the local date.
+    // 1 on a public holiday or a holiday in the user's calendar
+    IsHoliday,
+    // 1 during an away period in the user's calendar
+    IsAway,
+    // Days until the next holiday over DAYS_TO_HOLIDAY_HORIZON, capped at 1
+    DaysToHoliday,
+}
+
+// Holidays further away than this all look the same
+const DAYS_TO_HOLIDAY_HORIZON: f64 = 28.0;
+
+impl Feature {
+    pub fn encoding(&self) -> Encoding {
+        match *self {
+            Feature::TimeOfDay { encoding }
+            | Feature::DayOfWeek { encoding }
+            | Feature::Month { encoding }
+            | Feature::DayOfYear { encoding } => encoding,
+            _ => Encoding::Linear,
+        }
+    }
+
+    // Features with a choice of encoding
+    fn is_periodic(&self) -> bool {
+        matches!(
+            self,
+            Feature::TimeOfDay { .. }
+                | Feature::DayOfWeek { .. }
+                | Feature::Month { .. }
+                | Feature::DayOfYear { .. }
+        )
+    }
+
+    pub fn is_solar(&self) -> bool {
+        matches!(
+            self,
+            Feature::SunElevation
+                | Feature::MinutesSinceSunset
+                | Feature::MinutesToSunrise
+                | Feature::IsDark
+                | Feature::MinutesSinceDusk
+                | Feature::MinutesToDawn
+        )
+    }
+
+    pub fn is_calendar(&self) -> bool {
+        matches!(
+            self,
+            Feature::IsHoliday | Feature::IsAway | Feature::DaysToHoliday
+        )
+    }
+
+    // Number of one-hot categories
+    fn categories(&self) -> usize {
+        match self {
+            Feature::TimeOfDay { .. } => 24,
+            Feature::DayOfWeek { .. } => 7,
+            Feature::Month { .. } => 12,
+            Feature::DayOfYear { .. } => 366,
+            _ => 1,
+        }
+    }
+
+    // Number of network inputs
+    pub fn width(&self) -> usize {
+        match self.encoding() {
+            Encoding::Linear => 1,
+            Encoding::Cyclic => 2,
+            Encoding::OneHot => self.categories(),
+        }
+    }
+
+    // The value in [0, period), the period, and the divisor used for linear encoding, for
+    // the periodic features
+    fn value(&self, time: &DateTime<Tz>) -> Option<(f64, f64, f64)> {
+        match self {
+            // time of day normalised so 00:00 is 0 and 24:00 would be 1
+            Feature::TimeOfDay { .. } => {
+                let mins = (time.minute() + 60 * time.hour()) as f64;
+                Some((mins, 1440.0, 1440.0))
+            }
+            // weekdays, months and days with the last one mapped to 1
+            Feature::DayOfWeek { .. } => {
+                Some((time.weekday().num_days_from_monday() as f64, 7.0, 6.0))
+            }
+            Feature::Month { .. } => Some((time.month0() as f64, 12.0, 11.0)),
+            Feature::DayOfYear { .. } => {
+                let days = days_in_year(time.year());
+                Some((time.ordinal0() as f64, days, days - 1.0))
+            }
+            _ => None,
+        }
+    }
+
+    fn solar_value(&self, location: &Location, time: DateTime<Utc>) -> f64 {
+        // Minutes between time and the last or next event over a day, 0 while the sun is
+        // above altitude. No event within two days is a polar night (or day)
+        let minutes = |altitude: f64, event: SunEvent, since: bool| {
+            if solar::elevation(location, time) >= altitude {
+                return 0.0;
+            }
+            let other = if since {
+                solar::last(location, time, event)
+            } else {
+                solar::next(location, time, event)
+            };
+            other.map_or(1.0, |other| {
+                ((time - other).num_minutes().abs() as f64 / 1440.0).min(1.0)
+            })
+        };
+        match self {
+            Feature::SunElevation => solar::elevation(location, time) / 90.0,
+            Feature::MinutesSinceSunset => minutes(SUNRISE_ELEVATION, |t| t.sunset, true),
+            Feature::MinutesToSunrise => minutes(SUNRISE_ELEVATION, |t| t.sunrise, false),
+            Feature::IsDark => flag(solar::is_dark(location, time)),
+            Feature::MinutesSinceDusk => minutes(CIVIL_TWILIGHT_ELEVATION, |t| t.dusk, true),
+            Feature::MinutesToDawn => minutes(CIVIL_TWILIGHT_ELEVATION, |t| t.dawn, false),
+            _ => unreachable!("{} is not a solar feature", self),
+        }
+    }
+
+    fn calendar_value(&self, calendar: &Calendar, date: NaiveDate) -> f64 {
+        match self {
+            Feature::IsHoliday => flag(calendar.is_holiday(date)),
+            Feature::IsAway => flag(calendar.is_away(date)),
+            Feature::DaysToHoliday => calendar
+                .days_to_holiday(date)
+                .map_or(1.0, |days| (days as f64 / DAYS_TO_HOLIDAY_HORIZON).min(1.0)),
+            _ => unreachable!("{} is not a calendar feature", self),
+        }
+    }
+
+    fn encode(&self, time: &DateTime<Tz>, spec: &FeatureSpec, features: &mut Vec<f64>) {
+        let Some((value, period, scale)) = self.value(time) else {
+            // FeatureSpec::validate rejects features without the location or calendar they need
+            let value = if self.is_solar() {
+                let location = spec
+                    .location
+                    .as_ref()
+                    .expect("solar features need a location");
+                self.solar_value(location, time.to_utc())
+            } else {
+                let calendar = spec
+                    .calendar
+                    .as_ref()
+                    .expect("calendar features need a calendar");
+                self.calendar_value(calendar, time.date_naive())
+            };
+            features.push(value);
+            return;
+        };
+        match self.encoding() {
+            Encoding::Linear => features.push(value / scale),
+            Encoding::Cyclic => {
+                let angle = TAU * value / period;
+                features.push(angle.sin());
+                features.push(angle.cos());
+            }
+            Encoding::OneHot => {
+                let n = self.categories();
+                let hot = ((value / period * n as f64) as usize).min(n - 1);
+                features.extend((0..n).map(|i| if i == hot { 1.0 } else { 0.0 }));
+            }
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Feature::TimeOfDay { .. } => "time-of-day",
+            Feature::DayOfWeek { .. } => "day-of-week",
+            Feature::Month { .. } => "month",
+            Feature::DayOfYear { .. } => "day-of-year",
+            Feature::SunElevation => "sun-elevation",
+            Feature::MinutesSinceSunset => "minutes-since-sunset",
+            Feature::MinutesToSunrise => "minutes-to-sunrise",
+            Feature::IsDark => "is-dark",
+            Feature::MinutesSinceDusk => "minutes-since-dusk",
+            Feature::MinutesToDawn => "minutes-to-dawn",
+            Feature::IsHoliday => "is-holiday",
+            Feature::IsAway => "is-away",
+            Feature::DaysToHoliday => "days-to-holiday",
+        }
+    }
+}
+
+fn flag(value: bool) -> f64 {
+    if value { 1.0 } else { 0.0 }
+}
+
+fn days_in_year(year: i32) -> f64 {
+    if NaiveDate::from_ymd_opt(year, 2, 29).is_some() {
+        366.0
+    } else {
+        365.0
+    }
+}
+
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.is_periodic() {
+            return write!(f, "{}", self.name());
+        }
+        let encoding = match self.encoding() {
+            Encoding::Linear => "linear",
+            Encoding::Cyclic => "cyclic",
+            Encoding::OneHot => "one-hot",
+        };
+        write!(f, "{}:{}", self.name(), encoding)
+    }
+}
+
+// A feature name with an optional encoding, e.g. "month", "time-of-day:cyclic" or "is-dark"
+impl FromStr for Feature {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, encoding) = s.split_once(':').unwrap_or((s, "linear"));
+        let encoding = match encoding.trim() {
+            "linear" => Encoding::Linear,
+            "cyclic" => Encoding::Cyclic,
+            "one-hot" => Encoding::OneHot,
+            other => return Err(format!("Unknown encoding {} in {}", other, s)),
+        };
+        let feature = match name.trim() {
+            "time-of-day" => Feature::TimeOfDay { encoding },
+            "day-of-week" => Feature::DayOfWeek { encoding },
+            "month" => Feature::Month { encoding },
+            "day-of-year" => Feature::DayOfYear { encoding },
+            "sun-elevation" => Feature::SunElevation,
+            "minutes-since-sunset" => Feature::MinutesSinceSunset,
+            "minutes-to-sunrise" => Feature::MinutesToSunrise,
+            "is-dark" => Feature::IsDark,
+            "minutes-since-dusk" => Feature::MinutesSinceDusk,
+            "minutes-to-dawn" => Feature::MinutesToDawn,
+            "is-holiday" => Feature::IsHoliday,
+            "is-away" => Feature::IsAway,
+            "days-to-holiday" => Feature::DaysToHoliday,
+            other => return Err(format!("Unknown feature {} in {}", other, s)),
+        };
+        if !feature.is_periodic() && encoding != Encoding::Linear {
+            return Err(format!("{} can only be linear in {}", feature.name(), s));
+        }
+        Ok(feature)
+    }
+}
+
+// The features fed to the network, stored with the model so the inputs it was trained on
+// can be rebuilt. Features are taken from the wall-clock time in timezone, so the pattern
+// the network learns doesn't shift by an hour at every daylight saving change. The solar
+// features are computed for location and the calendar features from calendar
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(from = "StoredFeatureSpec")]
+pub struct FeatureSpec {
+    pub features: Vec<Feature>,
+    pub timezone: Tz,
+    pub location: Option<Location>,
+    pub calendar: Option<Calendar>,
+}
+
+impl Default for FeatureSpec {
+    // The original inputs: time of day, day of week and month, all linear, in UTC
+    fn default() -> Self {
+        let encoding = Encoding::Linear;
+        Self {
+            features: vec![
+                Feature::TimeOfDay { encoding },
+                Feature::DayOfWeek { encoding },
+                Feature::Month { encoding },
+            ],
+            timezone: Tz::UTC,
+            location: None,
+            calendar: None,
+        }
+    }
+}
+
+// Accepts the bare list of features written before the timezone was stored
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredFeatureSpec {
+    Features(Vec<Feature>),
+    Spec {
+        features: Vec<Feature>,
+        timezone: Tz,
+        #[serde(default)]
+        location: Option<Location>,
+        #[serde(default)]
+        calendar: Option<Calendar>,
+    },
+}
+
+impl From<StoredFeatureSpec> for FeatureSpec {
+    fn from(stored: StoredFeatureSpec) -> Self {
+        match stored {
+            StoredFeatureSpec::Features(features) => Self {
+                features,
+                timezone: Tz::UTC,
+                location: None,
+                calendar: None,
+            },
+            StoredFeatureSpec::Spec {
+                features,
+                timezone,
+                location,
+                calendar,
+            } => Self {
+                features,
+                timezone,
+                location,
+                calendar,
+            },
+        }
+    }
+}
+
+impl FeatureSpec {
+    // Number of network inputs
+    pub fn width(&self) -> usize {
+        self.features.iter().map(Feature::width).sum()
+    }
+
+    // Solar features can't be computed without a valid location, or calendar ones
+    // without a calendar
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(location) = &self.location {
+            location.validate()?;
+        }
+        if self.location.is_none() && self.features.iter().any(Feature::is_solar) {
+            return Err(format!("{} need a location", self));
+        }
+        if self.calendar.is_none() && self.features.iter().any(Feature::is_calendar) {
+            return Err(format!("{} need a country or calendar", self));
+        }
+        Ok(())
+    }
+
+    pub fn make(&self, sample: &LightSample) -> Vec<f64> {
+        let time = sample.time.with_timezone(&self.timezone);
+        let mut features = Vec::with_capacity(self.width());
+        for feature in &self.features {
+            feature.encode(&time, self, &mut features);
+        }
+        features
+    }
+}
+
+impl fmt::Display for FeatureSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<String> = self.features.iter().map(Feature::to_string).collect();
+        write!(f, "{} in {}", names.join(","), self.timezone)?;
+        if let Some(location) = &self.location {
+            write!(f, " at {}", location)?;
+        }
+        if let Some(calendar) = &self.calendar {
+            write!(f, " with {}", calendar)?;
+        }
+        Ok(())
+    }
 }
@@ -1,4 +1,8 @@
+pub mod calendar;
 pub mod idg;
+pub mod solar;
+pub mod split;
+pub mod timezone;
 pub mod tsg;
 pub mod types;
 
@@ -0,0 +1,189 @@
+use std::{fmt, str::FromStr};
+
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+
+// Sun position from the NOAA solar calculator equations (after Meeus), good to about a
+// minute for sunrise and sunset between 1900 and 2100. Everything is computed locally
+
+// Elevation of the sun's centre at sunrise and sunset: the upper limb is on the horizon
+// and refraction lifts it by about 34'
+pub const SUNRISE_ELEVATION: f64 = -0.833;
+// Civil twilight ends (dusk) and starts (dawn) with the sun 6 degrees below the horizon
+pub const CIVIL_TWILIGHT_ELEVATION: f64 = -6.0;
+
+// A place on the earth in degrees, north and east positive
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Location {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl Location {
+    // Locations read from a model file haven't been through from_str
+    pub fn validate(&self) -> Result<(), String> {
+        // NaN is out of range too
+        if !(-90.0..=90.0).contains(&self.latitude) {
+            return Err(format!("Latitude {} is out of range", self.latitude));
+        }
+        if !(-180.0..=180.0).contains(&self.longitude) {
+            return Err(format!("Longitude {} is out of range", self.longitude));
+        }
+        Ok(())
+    }
+}
+
+// "latitude,longitude", e.g. "51.5,-0.13"
+impl FromStr for Location {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |v: Option<&str>| {
+            v.and_then(|v| v.trim().parse::<f64>().ok())
+                .ok_or(format!("Expected latitude,longitude but got {}", s))
+        };
+        let mut parts = s.split(',');
+        let location = Self {
+            latitude: parse(parts.next())?,
+            longitude: parse(parts.next())?,
+        };
+        if parts.next().is_some() {
+            return Err(format!("Expected latitude,longitude but got {}", s));
+        }
+        location
+            .validate()
+            .map_err(|message| format!("{} in {}", message, s))?;
+        Ok(location)
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{}", self.latitude, self.longitude)
+    }
+}
+
+// The sun's declination in degrees and the equation of time in minutes
+fn sun_position(time: DateTime<Utc>) -> (f64, f64) {
+    let julian_day = time.timestamp() as f64 / 86400.0 + 2440587.5;
+    let t = (julian_day - 2451545.0) / 36525.0;
+
+    let mean_longitude = (280.46646 + t * (36000.76983 + t * 0.0003032)).rem_euclid(360.0);
+    let mean_anomaly = 357.52911 + t * (35999.05029 - 0.0001537 * t);
+    let eccentricity = 0.016708634 - t * (0.000042037 + 0.0000001267 * t);
+    let m = mean_anomaly.to_radians();
+    let centre = m.sin() * (1.914602 - t * (0.004817 + 0.000014 * t))
+        + (2.0 * m).sin() * (0.019993 - 0.000101 * t)
+        + (3.0 * m).sin() * 0.000289;
+    let omega = (125.04 - 1934.136 * t).to_radians();
+    let apparent_longitude =
+        (mean_longitude + centre - 0.00569 - 0.00478 * omega.sin()).to_radians();
+    let mean_obliquity =
+        23.0 + (26.0 + (21.448 - t * (46.815 + t * (0.00059 - t * 0.001813))) / 60.0) / 60.0;
+    let obliquity = (mean_obliquity + 0.00256 * omega.cos()).to_radians();
+
+    let declination = (obliquity.sin() * apparent_longitude.sin()).asin();
+
+    let y = (obliquity / 2.0).tan().powi(2);
+    let l = mean_longitude.to_radians();
+    let equation_of_time = 4.0
+        * (y * (2.0 * l).sin() - 2.0 * eccentricity * m.sin()
+            + 4.0 * eccentricity * y * m.sin() * (2.0 * l).cos()
+            - 0.5 * y * y * (4.0 * l).sin()
+            - 1.25 * eccentricity * eccentricity * (2.0 * m).sin())
+        .to_degrees();
+
+    (declination.to_degrees(), equation_of_time)
+}
+
+// Geometric elevation of the sun's centre above the horizon in degrees, without refraction
+pub fn elevation(location: &Location, time: DateTime<Utc>) -> f64 {
+    let (declination, equation_of_time) = sun_position(time);
+    let minutes = time.num_seconds_from_midnight() as f64 / 60.0;
+    let solar_time = minutes + equation_of_time + 4.0 * location.longitude;
+    let hour_angle = (solar_time / 4.0 - 180.0).to_radians();
+    let (lat, dec) = (location.latitude.to_radians(), declination.to_radians());
+    let cos_zenith = lat.sin() * dec.sin() + lat.cos() * dec.cos() * hour_angle.cos();
+    90.0 - cos_zenith.clamp(-1.0, 1.0).acos().to_degrees()
+}
+
+// When the sun's centre passes through the altitude on the given UTC date, rising if
+// rising is true. None if it stays above or below it all day
+fn crossing(
+    location: &Location,
+    date: NaiveDate,
+    altitude: f64,
+    rising: bool,
+) -> Option<DateTime<Utc>> {
+    let midnight = date.and_time(NaiveTime::MIN).and_utc();
+    let minutes_to_time =
+        |minutes: f64| midnight + Duration::seconds((minutes * 60.0).round() as i64);
+    let lat = location.latitude.to_radians();
+
+    // start from solar noon, then refine with the sun's position at the previous estimate
+    let mut estimate = midnight + Duration::hours(12);
+    for _ in 0..3 {
+        let (declination, equation_of_time) = sun_position(estimate);
+        let dec = declination.to_radians();
+        let cos_hour_angle =
+            (altitude.to_radians().sin() - lat.sin() * dec.sin()) / (lat.cos() * dec.cos());
+        if cos_hour_angle.abs() > 1.0 {
+            return None;
+        }
+        let hour_angle = cos_hour_angle.acos().to_degrees();
+        let noon = 720.0 - 4.0 * location.longitude - equation_of_time;
+        let offset = 4.0 * hour_angle;
+        estimate = minutes_to_time(if rising { noon - offset } else { noon + offset });
+    }
+    Some(estimate)
+}
+
+// Times the sun crosses the horizon and the civil twilight limit around solar noon on a
+// UTC date. Away from the poles each is one instant; None when the sun doesn't cross
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SunTimes {
+    pub dawn: Option<DateTime<Utc>>,
+    pub sunrise: Option<DateTime<Utc>>,
+    pub sunset: Option<DateTime<Utc>>,
+    pub dusk: Option<DateTime<Utc>>,
+}
+
+impl SunTimes {
+    pub fn new(location: &Location, date: NaiveDate) -> Self {
+        Self {
+            dawn: crossing(location, date, CIVIL_TWILIGHT_ELEVATION, true),
+            sunrise: crossing(location, date, SUNRISE_ELEVATION, true),
+            sunset: crossing(location, date, SUNRISE_ELEVATION, false),
+            dusk: crossing(location, date, CIVIL_TWILIGHT_ELEVATION, false),
+        }
+    }
+}
+
+// Picks one of the SunTimes, e.g. |t| t.sunset
+pub type SunEvent = fn(&SunTimes) -> Option<DateTime<Utc>>;
+
+// The latest time of the event at or before time, looking back up to two days. At high
+// latitudes there may be none
+pub fn last(location: &Location, time: DateTime<Utc>, event: SunEvent) -> Option<DateTime<Utc>> {
+    // the times for a UTC date can fall on the next day west of Greenwich
+    let today = time.date_naive();
+    (-2..=1)
+        .filter_map(|d| event(&SunTimes::new(location, today + Duration::days(d))))
+        .filter(|&t| t <= time)
+        .max()
+}
+
+// The earliest time of the event after time, looking ahead up to two days
+pub fn next(location: &Location, time: DateTime<Utc>, event: SunEvent) -> Option<DateTime<Utc>> {
+    // and on the previous day east of Greenwich
+    let today = time.date_naive();
+    (-1..=2)
+        .filter_map(|d| event(&SunTimes::new(location, today + Duration::days(d))))
+        .filter(|&t| t > time)
+        .min()
+}
+
+// Whether it's darker than civil twilight, when most people want a light on indoors
+pub fn is_dark(location: &Location, time: DateTime<Utc>) -> bool {
+    elevation(location, time) < CIVIL_TWILIGHT_ELEVATION
+}
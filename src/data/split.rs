@@ -0,0 +1,89 @@
+use std::ops::Range;
+
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, Utc};
+use chrono_tz::Tz;
+
+use super::{timezone::local_to_utc, types::LightSample};
+
+// Time-aware dataset splitting. Neighbouring 15 minute samples are strongly
+// correlated, so a random shuffle leaks information between train and test.
+// These splits never train on anything later than the data being tested.
+//
+// Forecasting: Principles and Practice - time series cross-validation
+// https://otexts.com/fpp3/tscv.html
+
+// Splits time-ordered samples into those before the cutoff and those at or after it
+pub fn split_at(
+    samples: Vec<LightSample>,
+    cutoff: DateTime<Utc>,
+) -> (Vec<LightSample>, Vec<LightSample>) {
+    samples.into_iter().partition(|s| s.time < cutoff)
+}
+
+// Splits at local midnight in tz at the start of the given date
+pub fn split_at_date(
+    samples: Vec<LightSample>,
+    date: NaiveDate,
+    tz: &Tz,
+) -> (Vec<LightSample>, Vec<LightSample>) {
+    split_at(samples, local_to_utc(tz, date.and_time(NaiveTime::MIN)))
+}
+
+// One rolling-origin fold: train on every sample before the test period, then test on it.
+// Ranges index into the samples passed to walk_forward
+#[derive(Debug, PartialEq)]
+pub struct Fold {
+    pub train: Range<usize>,
+    pub test: Range<usize>,
+    pub test_start: DateTime<Utc>,
+    pub test_end: DateTime<Utc>,
+}
+
+// Divides time-ordered samples into periods of the given length of wall-clock time in
+// tz, starting at local midnight before the first sample, so periods of whole days
+// start at midnight even across daylight saving changes. Fold k trains on periods
+// 1..=k and tests on period k + 1, beginning once min_train_periods periods are
+// available for training. Periods without any samples produce no fold. The period must
+// be positive, or the folds would never pass the last sample
+pub fn walk_forward(
+    samples: &[LightSample],
+    period: Duration,
+    min_train_periods: usize,
+    tz: &Tz,
+) -> Vec<Fold> {
+    assert!(
+        period > Duration::zero(),
+        "Walk-forward period must be positive"
+    );
+    let (Some(first), Some(last)) = (samples.first(), samples.last()) else {
+        return vec![];
+    };
+    let origin = first
+        .time
+        .with_timezone(tz)
+        .date_naive()
+        .and_time(NaiveTime::MIN);
+    let period_start = |k: i32| local_to_utc(tz, origin + period * k);
+
+    let mut folds = vec![];
+    let mut k = min_train_periods.max(1) as i32;
+    loop {
+        let test_start = period_start(k);
+        if test_start > last.time {
+            break;
+        }
+        let test_end = period_start(k + 1);
+        let start = samples.partition_point(|s| s.time < test_start);
+        let end = samples.partition_point(|s| s.time < test_end);
+        if start > 0 && end > start {
+            folds.push(Fold {
+                train: 0..start,
+                test: start..end,
+                test_start,
+                test_end,
+            });
+        }
+        k += 1;
+    }
+    folds
+}
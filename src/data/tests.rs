@@ -1,11 +1,23 @@
 #[cfg(test)]
+use super::calendar::{Calendar, Country, EventKind, easter, parse_ics};
+#[cfg(test)]
+use super::idg::{Encoding, Feature, FeatureSpec};
+#[cfg(test)]
+use super::solar::{Location, SunTimes, elevation};
+#[cfg(test)]
+use super::split::{split_at_date, walk_forward};
+#[cfg(test)]
+use super::timezone::local_to_utc;
+#[cfg(test)]
 use super::types::LightSample;
 #[cfg(test)]
 use crate::data::tsg::LightTimeSeriesGenerator;
 #[cfg(test)]
 use crate::db::{LightEvent, LightState};
 #[cfg(test)]
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+#[cfg(test)]
+use chrono_tz::Tz;
 
 // Helper for tests
 #[cfg(test)]
@@ -138,3 +150,623 @@ fn test_light_time_series_generator_resumes_after_waiting_for_a_new_event() {
         tsg.next()
     );
 }
+
+// Hourly samples starting at the given time
+#[cfg(test)]
+fn make_hourly_samples(start: &str, hours: i64) -> Vec<LightSample> {
+    let start = make_lightsample(LightState::Off, start).time;
+    (0..hours)
+        .map(|h| LightSample {
+            state: LightState::Off,
+            time: start + Duration::hours(h),
+        })
+        .collect()
+}
+
+#[test]
+fn test_split_at_date_puts_midnight_in_the_later_half() {
+    let samples = make_hourly_samples("2023-03-01 20:00:00", 8);
+    let (before, after) = split_at_date(
+        samples,
+        NaiveDate::from_ymd_opt(2023, 3, 2).unwrap(),
+        &Tz::UTC,
+    );
+    assert_eq!(before.len(), 4);
+    assert_eq!(
+        after.first(),
+        Some(&make_lightsample(LightState::Off, "2023-03-02 00:00:00"))
+    );
+}
+
+#[test]
+fn test_walk_forward_trains_on_all_earlier_periods() {
+    // 3 weeks of hourly samples starting mid-morning on the first day
+    let samples = make_hourly_samples("2023-03-01 10:00:00", 21 * 24 - 10);
+    let folds = walk_forward(&samples, Duration::days(7), 1, &Tz::UTC);
+    assert_eq!(folds.len(), 2);
+
+    // The first week is short because sampling started at 10:00
+    assert_eq!(folds[0].train, 0..7 * 24 - 10);
+    assert_eq!(folds[0].test, 7 * 24 - 10..14 * 24 - 10);
+    assert_eq!(
+        folds[0].test_start,
+        make_lightsample(LightState::Off, "2023-03-08 00:00:00").time
+    );
+    assert_eq!(folds[1].train, 0..14 * 24 - 10);
+    assert_eq!(folds[1].test.end, samples.len());
+}
+
+#[test]
+fn test_walk_forward_skips_empty_periods_and_respects_min_train_periods() {
+    let mut samples = make_hourly_samples("2023-03-01 00:00:00", 24);
+    samples.extend(make_hourly_samples("2023-03-04 00:00:00", 24));
+    samples.extend(make_hourly_samples("2023-03-05 00:00:00", 24));
+    let folds = walk_forward(&samples, Duration::days(1), 2, &Tz::UTC);
+    // Mar 2 and Mar 3 have no samples; Mar 1 alone is one period so Mar 2 would be first
+    assert_eq!(folds.len(), 2);
+    assert_eq!(folds[0].train, 0..24);
+    assert_eq!(folds[0].test, 24..48);
+    assert_eq!(folds[1].test, 48..72);
+    assert!(walk_forward(&[], Duration::days(1), 1, &Tz::UTC).is_empty());
+}
+
+#[test]
+#[should_panic(expected = "period must be positive")]
+fn test_walk_forward_rejects_empty_periods() {
+    let samples = make_hourly_samples("2023-03-01 00:00:00", 24);
+    walk_forward(&samples, Duration::zero(), 1, &Tz::UTC);
+}
+
+#[test]
+fn test_splits_follow_local_midnight() {
+    let tz = Tz::Europe__London;
+    // London is an hour ahead of UTC in June, so local midnight is 23:00 UTC
+    let samples = make_hourly_samples("2023-06-01 20:00:00", 8);
+    let (before, after) = split_at_date(samples, NaiveDate::from_ymd_opt(2023, 6, 2).unwrap(), &tz);
+    assert_eq!(before.len(), 3);
+    assert_eq!(
+        after.first(),
+        Some(&make_lightsample(LightState::Off, "2023-06-01 23:00:00"))
+    );
+
+    // Clocks go forward on 26 March 2023, so that day is 23 hours long and the next
+    // period starts at 23:00 UTC rather than 24 hours after the last
+    let samples = make_hourly_samples("2023-03-25 12:00:00", 72);
+    let folds = walk_forward(&samples, Duration::days(1), 1, &tz);
+    assert_eq!(
+        folds[0].test_start,
+        make_lightsample(LightState::Off, "2023-03-26 00:00:00").time
+    );
+    assert_eq!(
+        folds[1].test_start,
+        make_lightsample(LightState::Off, "2023-03-26 23:00:00").time
+    );
+}
+
+#[test]
+fn test_default_features_are_scaled_to_unit_range() {
+    let features =
+        FeatureSpec::default().make(&make_lightsample(LightState::On, "2023-12-31 18:00:00"));
+    // 18:00 on a Sunday in December
+    assert_eq!(features, vec![0.75, 1.0, 1.0]);
+}
+
+#[test]
+fn test_cyclic_features_wrap_around() {
+    let encoding = Encoding::Cyclic;
+    let spec = FeatureSpec {
+        features: vec![
+            Feature::TimeOfDay { encoding },
+            Feature::DayOfWeek { encoding },
+            Feature::Month { encoding },
+            Feature::DayOfYear { encoding },
+        ],
+        timezone: Tz::UTC,
+        location: None,
+        calendar: None,
+    };
+    assert_eq!(spec.width(), 8);
+    // distance between two times in the sin/cos plane of one feature
+    let distance = |feature: usize, a: &str, b: &str| {
+        let a = spec.make(&make_lightsample(LightState::On, a));
+        let b = spec.make(&make_lightsample(LightState::On, b));
+        let i = 2 * feature;
+        ((a[i] - b[i]).powi(2) + (a[i + 1] - b[i + 1]).powi(2)).sqrt()
+    };
+
+    // Crossing midnight on New Year's Eve is as small a step in every feature as the same
+    // step at any other time
+    let (before, after) = ("2023-12-31 23:55:00", "2024-01-01 00:00:00");
+    let (earlier, later) = ("2023-06-05 11:55:00", "2023-06-05 12:00:00");
+    assert!((distance(0, before, after) - distance(0, earlier, later)).abs() < 1e-9);
+    // Sunday to Monday, December to January
+    let monday = "2023-06-05 12:00:00";
+    let tuesday = "2023-06-06 12:00:00";
+    assert!((distance(1, before, after) - distance(1, monday, tuesday)).abs() < 1e-9);
+    let july = "2023-07-05 12:00:00";
+    assert!((distance(2, before, after) - distance(2, monday, july)).abs() < 1e-9);
+    assert!(distance(3, before, after) < 0.02);
+    // whereas linear encodings jump from one end of the range to the other
+    let linear = FeatureSpec::default();
+    let a = linear.make(&make_lightsample(LightState::On, before));
+    let b = linear.make(&make_lightsample(LightState::On, after));
+    assert!(a.iter().zip(&b).all(|(a, b)| (a - b).abs() > 0.99));
+
+    // Midnight and noon are opposite points on the time of day circle
+    let f = spec.make(&make_lightsample(LightState::On, "2023-06-05 12:00:00"));
+    assert!(f[0].abs() < 1e-12);
+    assert!((f[1] + 1.0).abs() < 1e-12);
+}
+
+#[test]
+fn test_feature_spec_parses_and_serializes() {
+    let features: Vec<Feature> = ["time-of-day:cyclic", "day-of-week:one-hot", "month"]
+        .iter()
+        .map(|s| s.parse().unwrap())
+        .collect();
+    let spec = FeatureSpec {
+        features,
+        timezone: Tz::Europe__Paris,
+        location: None,
+        calendar: None,
+    };
+    assert_eq!(spec.width(), 2 + 7 + 1);
+    assert_eq!(
+        spec.to_string(),
+        "time-of-day:cyclic,day-of-week:one-hot,month:linear in Europe/Paris"
+    );
+    assert!("week:cyclic".parse::<Feature>().is_err());
+    assert!("month:log".parse::<Feature>().is_err());
+
+    // Wednesday is the third weekday
+    let f = spec.make(&make_lightsample(LightState::On, "2023-03-01 06:00:00"));
+    assert_eq!(f.len(), spec.width());
+    assert_eq!(&f[2..9], &[0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0]);
+
+    let features = r#"[{"type":"time_of_day","encoding":"cyclic"},{"type":"day_of_week","encoding":"one_hot"},{"type":"month","encoding":"linear"}]"#;
+    let json = serde_json::to_string(&spec).unwrap();
+    assert_eq!(
+        json,
+        format!(
+            r#"{{"features":{},"timezone":"Europe/Paris","location":null,"calendar":null}}"#,
+            features
+        )
+    );
+    assert_eq!(serde_json::from_str::<FeatureSpec>(&json).unwrap(), spec);
+
+    // A bare list of features, as stored before the timezone was added, is read as UTC
+    let old: FeatureSpec = serde_json::from_str(features).unwrap();
+    assert_eq!(old.features, spec.features);
+    assert_eq!(old.timezone, Tz::UTC);
+}
+
+// The linear time of day feature for a UTC time, in minutes past local midnight
+#[cfg(test)]
+fn local_minutes(spec: &FeatureSpec, date_and_time: &str) -> f64 {
+    spec.make(&make_lightsample(LightState::On, date_and_time))[0] * 1440.0
+}
+
+#[test]
+fn test_features_use_local_wall_clock_time() {
+    let london = FeatureSpec {
+        timezone: Tz::Europe__London,
+        ..FeatureSpec::default()
+    };
+    // 23:30 UTC on Sunday 30 June is 00:30 BST on Monday 1 July
+    let f = london.make(&make_lightsample(LightState::On, "2024-06-30 23:30:00"));
+    assert_eq!(f, vec![30.0 / 1440.0, 0.0, 6.0 / 11.0]);
+    let f = FeatureSpec::default().make(&make_lightsample(LightState::On, "2024-06-30 23:30:00"));
+    assert_eq!(f, vec![1410.0 / 1440.0, 1.0, 5.0 / 11.0]);
+    // in winter London is on UTC
+    assert_eq!(local_minutes(&london, "2024-01-15 23:30:00"), 1410.0);
+}
+
+#[test]
+fn test_skipped_hour_when_clocks_go_forward() {
+    // London clocks go from 01:00 GMT to 02:00 BST on 31 March 2024
+    let london = FeatureSpec {
+        timezone: Tz::Europe__London,
+        ..FeatureSpec::default()
+    };
+    assert_eq!(local_minutes(&london, "2024-03-31 00:45:00"), 45.0);
+    assert_eq!(local_minutes(&london, "2024-03-31 01:00:00"), 120.0);
+    // no sample reads 01:xx local
+    let samples = make_hourly_samples("2024-03-31 00:00:00", 24);
+    let hours: Vec<f64> = samples
+        .iter()
+        .map(|s| (london.make(s)[0] * 24.0).round())
+        .collect();
+    assert_eq!(&hours[..3], &[0.0, 2.0, 3.0]);
+    assert!(hours.iter().all(|&h| h != 1.0));
+
+    // a wall-clock time in the gap is read with the GMT offset, landing at 02:30 BST
+    let gap = NaiveDate::from_ymd_opt(2024, 3, 31)
+        .unwrap()
+        .and_hms_opt(1, 30, 0)
+        .unwrap();
+    let utc = local_to_utc(&Tz::Europe__London, gap);
+    assert_eq!(utc.naive_utc(), gap);
+    assert_eq!(
+        local_minutes(&london, &utc.format("%Y-%m-%d %H:%M:%S").to_string()),
+        150.0
+    );
+}
+
+#[test]
+fn test_repeated_hour_when_clocks_go_back() {
+    // London clocks go from 02:00 BST back to 01:00 GMT on 27 October 2024, so 01:30
+    // happens at both 00:30 and 01:30 UTC
+    let london = FeatureSpec {
+        timezone: Tz::Europe__London,
+        ..FeatureSpec::default()
+    };
+    let first = london.make(&make_lightsample(LightState::On, "2024-10-27 00:30:00"));
+    let second = london.make(&make_lightsample(LightState::On, "2024-10-27 01:30:00"));
+    assert_eq!(first, second);
+    assert_eq!(first[0] * 1440.0, 90.0);
+    // 25 hourly samples cover the whole local day, 00:00 to 23:00, with 01:00 twice
+    let samples = make_hourly_samples("2024-10-26 23:00:00", 25);
+    let hours: Vec<f64> = samples
+        .iter()
+        .map(|s| (london.make(s)[0] * 24.0).round())
+        .collect();
+    assert_eq!(&hours[..4], &[0.0, 1.0, 1.0, 2.0]);
+    assert_eq!(hours[24], 23.0);
+
+    // the ambiguous wall-clock time resolves to the first occurrence
+    let repeated = NaiveDate::from_ymd_opt(2024, 10, 27)
+        .unwrap()
+        .and_hms_opt(1, 30, 0)
+        .unwrap();
+    let utc = local_to_utc(&Tz::Europe__London, repeated);
+    assert_eq!(utc.format("%H:%M").to_string(), "00:30");
+}
+
+// Checks a computed time against a published one, in UTC, to within two minutes
+#[cfg(test)]
+fn assert_near(time: Option<DateTime<Utc>>, expected: &str) {
+    let expected = make_lightsample(LightState::On, expected).time;
+    let time = time.unwrap();
+    assert!(
+        (time - expected).num_seconds().abs() <= 120,
+        "{} vs {}",
+        time,
+        expected
+    );
+}
+
+#[test]
+fn test_sun_times_match_published_values() {
+    let date = |d: &str| NaiveDate::parse_from_str(d, "%Y-%m-%d").unwrap();
+    let london: Location = "51.5074,-0.1278".parse().unwrap();
+    // 04:43 and 21:21 BST at midsummer
+    let t = SunTimes::new(&london, date("2024-06-21"));
+    assert_near(t.sunrise, "2024-06-21 03:43:00");
+    assert_near(t.sunset, "2024-06-21 20:21:00");
+    // 08:04 and 15:53 GMT at midwinter, with civil twilight from 07:24 to 16:33
+    let t = SunTimes::new(&london, date("2024-12-21"));
+    assert_near(t.dawn, "2024-12-21 07:24:00");
+    assert_near(t.sunrise, "2024-12-21 08:04:00");
+    assert_near(t.sunset, "2024-12-21 15:53:00");
+    assert_near(t.dusk, "2024-12-21 16:33:00");
+
+    // 05:25 and 20:31 EDT; sunset is on the next UTC day
+    let new_york: Location = "40.7128,-74.0060".parse().unwrap();
+    let t = SunTimes::new(&new_york, date("2024-06-21"));
+    assert_near(t.sunrise, "2024-06-21 09:25:00");
+    assert_near(t.sunset, "2024-06-22 00:31:00");
+    // 05:41 and 20:05 AEDT; sunrise is on the previous UTC day
+    let sydney: Location = "-33.8688,151.2093".parse().unwrap();
+    let t = SunTimes::new(&sydney, date("2024-12-21"));
+    assert_near(t.sunrise, "2024-12-20 18:41:00");
+    assert_near(t.sunset, "2024-12-21 09:05:00");
+
+    // Tromsø has midnight sun in June, and in December the sun stays below the horizon
+    // but it still gets light around midday
+    let tromso: Location = "69.6492,18.9553".parse().unwrap();
+    let t = SunTimes::new(&tromso, date("2024-06-21"));
+    assert_eq!(
+        (t.dawn, t.sunrise, t.sunset, t.dusk),
+        (None, None, None, None)
+    );
+    let t = SunTimes::new(&tromso, date("2024-12-21"));
+    assert_eq!((t.sunrise, t.sunset), (None, None));
+    assert!(t.dawn.is_some() && t.dusk.is_some());
+}
+
+#[test]
+fn test_sun_elevation() {
+    let london: Location = "51.5074,-0.1278".parse().unwrap();
+    let at = |t: &str| elevation(&london, make_lightsample(LightState::On, t).time);
+    // at solar noon on the solstices the sun is 90 - 51.51 +/- 23.44 degrees up
+    assert!((at("2024-06-21 12:02:00") - 61.93).abs() < 0.1);
+    assert!((at("2024-12-21 11:58:00") - 15.05).abs() < 0.1);
+    // and at midnight in midwinter it's 51.51 - 23.44 - 90 degrees
+    assert!((at("2024-12-22 00:00:00") + 61.93).abs() < 0.3);
+
+    assert!("51.5".parse::<Location>().is_err());
+    assert!("91,0".parse::<Location>().is_err());
+    assert!("51.5,-0.13,0".parse::<Location>().is_err());
+}
+
+#[test]
+fn test_solar_features() {
+    let spec = FeatureSpec {
+        features: ["sun-elevation", "minutes-since-sunset", "is-dark"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect(),
+        location: Some("51.5074,-0.1278".parse().unwrap()),
+        ..FeatureSpec::default()
+    };
+    assert_eq!(spec.width(), 3);
+    assert!(spec.validate().is_ok());
+    assert!("is-dark:cyclic".parse::<Feature>().is_err());
+    assert_eq!(
+        spec.to_string(),
+        "sun-elevation,minutes-since-sunset,is-dark in UTC at 51.5074,-0.1278"
+    );
+
+    // midday in winter
+    let f = spec.make(&make_lightsample(LightState::On, "2024-12-21 12:00:00"));
+    assert!(f[0] > 0.1);
+    assert_eq!(&f[1..], &[0.0, 0.0]);
+    // after sunset at 15:53 but before the end of civil twilight at 16:33
+    let f = spec.make(&make_lightsample(LightState::On, "2024-12-21 16:13:00"));
+    assert!(f[0] < 0.0);
+    assert!((f[1] * 1440.0 - 20.0).abs() <= 1.0);
+    assert_eq!(f[2], 0.0);
+    // dark, a little after 1 o'clock the next morning
+    let f = spec.make(&make_lightsample(LightState::On, "2024-12-22 01:23:00"));
+    assert!((f[1] * 1440.0 - 570.0).abs() <= 1.0);
+    assert_eq!(f[2], 1.0);
+
+    // the same features without a location are rejected
+    let spec = FeatureSpec {
+        location: None,
+        ..spec
+    };
+    assert!(spec.validate().is_err());
+    let json = serde_json::to_string(&spec).unwrap();
+    assert_eq!(serde_json::from_str::<FeatureSpec>(&json).unwrap(), spec);
+}
+
+#[test]
+fn test_sunrise_and_twilight_features() {
+    let spec = FeatureSpec {
+        features: [
+            "minutes-to-sunrise",
+            "minutes-since-dusk",
+            "minutes-to-dawn",
+        ]
+        .iter()
+        .map(|s| s.parse().unwrap())
+        .collect(),
+        location: Some("51.5074,-0.1278".parse().unwrap()),
+        ..FeatureSpec::default()
+    };
+    assert_eq!(
+        spec.to_string(),
+        "minutes-to-sunrise,minutes-since-dusk,minutes-to-dawn in UTC at 51.5074,-0.1278"
+    );
+
+    // all 0 at midday in winter
+    let f = spec.make(&make_lightsample(LightState::On, "2024-12-21 12:00:00"));
+    assert_eq!(f, vec![0.0, 0.0, 0.0]);
+    // after dusk at 16:33, with dawn at 07:24 and sunrise at 08:04 the next morning
+    let f = spec.make(&make_lightsample(LightState::On, "2024-12-22 01:23:00"));
+    assert!((f[0] * 1440.0 - 401.0).abs() <= 2.0);
+    assert!((f[1] * 1440.0 - 530.0).abs() <= 2.0);
+    assert!((f[2] * 1440.0 - 361.0).abs() <= 2.0);
+    // after sunset but not yet dusk
+    let f = spec.make(&make_lightsample(LightState::On, "2024-12-21 16:13:00"));
+    assert!(f[0] > 0.5);
+    assert_eq!(&f[1..], &[0.0, 0.0]);
+
+    // a location read from a model file is checked too
+    let mut json = serde_json::to_value(&spec).unwrap();
+    json["location"]["latitude"] = serde_json::json!(151.5);
+    let spec: FeatureSpec = serde_json::from_value(json).unwrap();
+    assert!(spec.validate().is_err());
+}
+
+#[cfg(test)]
+fn date(d: &str) -> NaiveDate {
+    NaiveDate::parse_from_str(d, "%Y-%m-%d").unwrap()
+}
+
+#[test]
+fn test_easter() {
+    for (year, sunday) in [
+        (1961, "1961-04-02"),
+        (2019, "2019-04-21"),
+        (2024, "2024-03-31"),
+        (2025, "2025-04-20"),
+        (2038, "2038-04-25"),
+    ] {
+        assert_eq!(easter(year), date(sunday));
+    }
+}
+
+#[test]
+fn test_public_holidays() {
+    let days = |country: Country, year| -> Vec<String> {
+        country
+            .public_holidays(year)
+            .iter()
+            .map(|(d, _)| d.format("%m-%d").to_string())
+            .collect()
+    };
+    assert_eq!(
+        days(Country::GB, 2024),
+        [
+            "01-01", "03-29", "04-01", "05-06", "05-27", "08-26", "12-25", "12-26"
+        ]
+    );
+    // Christmas on a Saturday and Boxing Day on a Sunday move to Monday and Tuesday, and
+    // New Year's Day 2022, also a Saturday, to Monday 3 January
+    assert_eq!(&days(Country::GB, 2021)[6..], ["12-27", "12-28"]);
+    assert_eq!(days(Country::GB, 2022)[0], "01-03");
+    // Christmas 2022 is a Sunday, but Boxing Day already has the Monday
+    assert_eq!(&days(Country::GB, 2022)[6..], ["12-26", "12-27"]);
+
+    assert_eq!(
+        days(Country::US, 2024),
+        [
+            "01-01", "01-15", "02-19", "05-27", "06-19", "07-04", "09-02", "10-14", "11-11",
+            "11-28", "12-25"
+        ]
+    );
+    // Saturday holidays are observed on the Friday, Sunday ones on the Monday
+    assert!(days(Country::US, 2026).contains(&"07-03".to_string()));
+    assert!(days(Country::US, 2022).contains(&"06-20".to_string()));
+    assert!(!days(Country::US, 2020).contains(&"06-19".to_string()));
+
+    assert_eq!(
+        days(Country::DE, 2024),
+        [
+            "01-01", "03-29", "04-01", "05-01", "05-09", "05-20", "10-03", "12-25", "12-26"
+        ]
+    );
+    assert_eq!(days(Country::FR, 2024).len(), 11);
+    assert!("xx".parse::<Country>().is_err());
+}
+
+#[cfg(test)]
+const ICS: &str = "BEGIN:VCALENDAR\r
+VERSION:2.0\r
+BEGIN:VEVENT\r
+SUMMARY:Autumn term\r
+DTSTART;VALUE=DATE:20240904\r
+DTEND;VALUE=DATE:20241219\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+SUMMARY:October half\r
+  term\r
+DTSTART;VALUE=DATE:20241028\r
+DTEND;VALUE=DATE:20241102\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+SUMMARY:Skiing\\, Alps\r
+CATEGORIES:AWAY\r
+DTSTART;TZID=Europe/London:20250215T060000\r
+DTEND;TZID=Europe/London:20250222T180000\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+SUMMARY:Christmas holidays\r
+DTSTART:20241220T000000Z\r
+DTEND:20250106T000000Z\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+
+#[test]
+fn test_parse_ics() {
+    let events = parse_ics(ICS).unwrap();
+    // term dates are skipped
+    assert_eq!(events.len(), 3);
+    assert_eq!(events[0].summary, "October half term");
+    assert_eq!(events[0].kind, EventKind::Holiday);
+    assert_eq!(
+        (events[0].start, events[0].end),
+        (date("2024-10-28"), date("2024-11-02"))
+    );
+    assert_eq!(events[1].summary, "Christmas holidays");
+    assert_eq!(events[1].end, date("2025-01-06"));
+    // an away period that ends during the day includes that day
+    assert_eq!(events[2].summary, "Skiing, Alps");
+    assert_eq!(events[2].kind, EventKind::Away);
+    assert_eq!(
+        (events[2].start, events[2].end),
+        (date("2025-02-15"), date("2025-02-23"))
+    );
+
+    // words and categories are matched whole
+    let event = |properties: &str| {
+        parse_ics(&format!(
+            "BEGIN:VEVENT\n{}\nDTSTART:20240101\nEND:VEVENT\n",
+            properties
+        ))
+        .unwrap()
+        .first()
+        .map(|e| e.kind)
+    };
+    assert_eq!(event("SUMMARY:Breakfast club"), None);
+    assert_eq!(event("SUMMARY:Giveaway"), None);
+    assert_eq!(event("SUMMARY:Half-term"), Some(EventKind::Holiday));
+    assert_eq!(event("SUMMARY:Easter break"), Some(EventKind::Holiday));
+    assert_eq!(
+        event("SUMMARY:Trip\nCATEGORIES:Family,Away"),
+        Some(EventKind::Away)
+    );
+    assert_eq!(event("SUMMARY:Trip\nCATEGORIES:Awayday"), None);
+    // recurring holidays aren't silently cut to their first occurrence
+    assert!(
+        parse_ics(
+            "BEGIN:VEVENT\nSUMMARY:Holiday\nDTSTART:20240101\nRRULE:FREQ=YEARLY\nEND:VEVENT\n"
+        )
+        .is_err()
+    );
+
+    assert!(parse_ics("BEGIN:VEVENT\nSUMMARY:Holiday\nEND:VEVENT\n").is_err());
+    assert!(parse_ics("BEGIN:VEVENT\nSUMMARY:Holiday\nDTSTART:2024\nEND:VEVENT\n").is_err());
+    assert!(parse_ics("BEGIN:VEVENT\nSUMMARY:Holiday\n").is_err());
+}
+
+#[test]
+fn test_calendar_features() {
+    let calendar = Calendar {
+        country: Some(Country::GB),
+        events: parse_ics(ICS).unwrap(),
+    };
+    assert!(calendar.is_holiday(date("2024-12-25")));
+    assert!(calendar.is_holiday(date("2024-10-31")));
+    assert!(!calendar.is_holiday(date("2024-11-02")));
+    assert!(calendar.is_away(date("2025-02-22")));
+    assert!(!calendar.is_away(date("2025-02-23")));
+    // the Summer bank holiday is a week after 19 August, half term starts 4 days after 24
+    // October, and the Christmas holidays are already under way on 1 January
+    assert_eq!(calendar.days_to_holiday(date("2024-08-19")), Some(7));
+    assert_eq!(calendar.days_to_holiday(date("2024-10-24")), Some(4));
+    assert_eq!(calendar.days_to_holiday(date("2025-01-01")), Some(0));
+
+    let spec = FeatureSpec {
+        features: ["is-holiday", "is-away", "days-to-holiday"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect(),
+        timezone: Tz::Europe__London,
+        calendar: Some(calendar),
+        ..FeatureSpec::default()
+    };
+    assert_eq!(spec.width(), 3);
+    assert!(spec.validate().is_ok());
+    assert!("is-holiday:one-hot".parse::<Feature>().is_err());
+    // London is back on GMT on 27 October, so 23:30 UTC is still the 27th, the day
+    // before half term
+    let f = spec.make(&make_lightsample(LightState::On, "2024-10-27 23:30:00"));
+    assert_eq!(f, vec![0.0, 0.0, 1.0 / 28.0]);
+    // Tokyo is 9 hours ahead of UTC, so half term starts there at 15:00 UTC on the 27th
+    // while it's still the 27th in UTC
+    let tokyo = FeatureSpec {
+        timezone: Tz::Asia__Tokyo,
+        ..spec.clone()
+    };
+    let f = tokyo.make(&make_lightsample(LightState::On, "2024-10-27 14:59:00"));
+    assert_eq!(f[0], 0.0);
+    let f = tokyo.make(&make_lightsample(LightState::On, "2024-10-27 15:00:00"));
+    assert_eq!(f[0], 1.0);
+    let f = spec.make(&make_lightsample(LightState::On, "2025-02-17 12:00:00"));
+    assert_eq!(&f[..2], &[0.0, 1.0]);
+    // the next holiday after February half term is Good Friday, too far off to count
+    assert_eq!(f[2], 1.0);
+
+    // the calendar is stored with the features
+    let json = serde_json::to_string(&spec).unwrap();
+    assert_eq!(serde_json::from_str::<FeatureSpec>(&json).unwrap(), spec);
+    let spec = FeatureSpec {
+        calendar: None,
+        ..spec
+    };
+    assert!(spec.validate().is_err());
+}
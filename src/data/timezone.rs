@@ -0,0 +1,20 @@
+use chrono::{DateTime, Duration, LocalResult, NaiveDateTime, Offset, TimeZone, Utc};
+use chrono_tz::Tz;
+
+// The instant at which the wall clock in tz reads local. A time in a repeated hour (clocks
+// going back) is taken as the first of the two; a time in a skipped hour (clocks going
+// forward) never happens, so it is read with the offset from before the change, which
+// moves it forward by the length of the gap
+pub fn local_to_utc(tz: &Tz, local: NaiveDateTime) -> DateTime<Utc> {
+    match tz.from_local_datetime(&local) {
+        LocalResult::Single(t) | LocalResult::Ambiguous(t, _) => t.with_timezone(&Utc),
+        LocalResult::None => {
+            // gaps are at most a few hours long, so this is before the change
+            let before = tz
+                .from_local_datetime(&(local - Duration::hours(6)))
+                .earliest()
+                .map_or(0, |t| t.offset().fix().local_minus_utc());
+            (local - Duration::seconds(before.into())).and_utc()
+        }
+    }
+}
@@ -106,22 +106,15 @@ impl Iterator for LightTimeSeriesGenerator {
             self.next_event = self.events.pop_front();
         }
 
-        if self.next_event.is_none() {
-            // we need more events
-            return None;
-        }
-
-        let mut max_sample_time = self.next_event.as_ref().unwrap().utc_datetime();
+        // we need more events
+        let mut max_sample_time = self.next_event.as_ref()?.utc_datetime();
 
         while self.sample_time >= max_sample_time {
             // We need to pull another event.  If there are no more events then we can't emit any more samples
-            let next_event = self.events.pop_front();
-            if next_event.is_none() {
-                // we need more events
-                return None;
-            }
+            // we need more events
+            let next_event = self.events.pop_front()?;
             self.state = self.next_event.as_ref().unwrap().on();
-            self.next_event = next_event;
+            self.next_event = Some(next_event);
             max_sample_time =
                 self.next_event.as_ref().unwrap().utc_datetime() - sample_interval_duration;
         }
@@ -86,7 +86,7 @@ impl LightEvent {
     }
 
     pub fn on(&self) -> bool {
-        return self.state == LightState::On;
+        self.state == LightState::On
     }
 }
 
@@ -94,8 +94,8 @@ impl LightEvent {
 pub async fn stream_query<'p>(
     db_pool: &'p Pool<Postgres>,
     sql_buf: &'p mut String,
-    from: Option<chrono::NaiveDate>,
-    to: Option<chrono::NaiveDate>,
+    from: Option<chrono::NaiveDateTime>,
+    to: Option<chrono::NaiveDateTime>,
 ) -> impl Stream<Item = Result<LightEvent, sqlx::Error>> + 'p {
     let mut bind_args = vec![];
 
@@ -115,7 +115,7 @@ d@>'{"id_v1": "/lights/3", "type": "light"}'"#,
     }
 
     if let Some(t) = to {
-        if bind_args.len() > 0 {
+        if !bind_args.is_empty() {
             sql_buf.push_str(" and creationtime <= $2 ");
         } else {
             sql_buf.push_str(" and creationtime <= $1 ")
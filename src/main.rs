@@ -11,7 +11,10 @@ use cmd::prelude::*;
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cli = cmd::cli::Cli::parse();
 
-    println!("debug level = {}", cli.debug);
+    // keep stdout clean for machine-readable output unless debugging
+    if cli.debug > 0 {
+        println!("debug level = {}", cli.debug);
+    }
 
     match &cli.command {
         Commands::Explore(args) => cmd::explore::run(args).await?,
@@ -19,6 +22,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         Commands::Import(args) => cmd::import::run(args)?,
         Commands::Train(args) => cmd::train::run(args).await?,
         Commands::Predict(args) => cmd::predict::run(args).await?,
+        Commands::Evaluate(args) => cmd::evaluate::run(args).await?,
+        Commands::Plot(args) => cmd::plot::run(args).await?,
     }
 
     Ok(())
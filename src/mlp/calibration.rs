@@ -0,0 +1,310 @@
+use serde::{Deserialize, Serialize};
+
+use super::fns::MLPFunc;
+
+// Probabilities are clamped to [EPSILON, 1 - EPSILON] before taking logits
+const EPSILON: f64 = 1e-12;
+
+// Maps raw network outputs to calibrated probabilities, fitted on held-out data
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Calibration {
+    #[default]
+    None,
+    // logistic(a * logit(p) + b), see Platt (1999)
+    Platt {
+        a: f64,
+        b: f64,
+    },
+    // Non-decreasing step function through (x, y) block means, interpolated linearly
+    // between them and held constant outside them
+    Isotonic {
+        x: Vec<f64>,
+        y: Vec<f64>,
+    },
+}
+
+impl Calibration {
+    pub fn apply(&self, p: f64) -> f64 {
+        match self {
+            Calibration::None => p,
+            Calibration::Platt { a, b } => MLPFunc::Logistic.function(a * logit(p) + b),
+            Calibration::Isotonic { x, y } => {
+                let i = x.partition_point(|&xi| xi < p);
+                if i == 0 {
+                    y[0]
+                } else if i == x.len() {
+                    y[x.len() - 1]
+                } else {
+                    let t = (p - x[i - 1]) / (x[i] - x[i - 1]);
+                    y[i - 1] + t * (y[i] - y[i - 1])
+                }
+            }
+        }
+    }
+
+    // Fits a and b by Newton's method on the cross-entropy, using Platt's smoothed targets
+    // so a separable set doesn't push a to infinity. Following Lin, Lin and Weng (2007),
+    // it starts from the prior log-odds and backtracks until each step lowers the loss,
+    // since a full step diverges on saturated outputs
+    pub fn platt(probabilities: &[f64], targets: &[f64]) -> Self {
+        let positives = targets.iter().filter(|&&y| y > 0.5).count() as f64;
+        let negatives = targets.len() as f64 - positives;
+        let high = (positives + 1.0) / (positives + 2.0);
+        let low = 1.0 / (negatives + 2.0);
+        let samples: Vec<(f64, f64)> = probabilities
+            .iter()
+            .zip(targets)
+            .map(|(&p, &y)| (logit(p), if y > 0.5 { high } else { low }))
+            .collect();
+
+        let loss = |a: f64, b: f64| -> f64 {
+            samples
+                .iter()
+                .map(|&(f, t)| {
+                    // softplus(z) - t * z, the cross-entropy of logistic(z) against t
+                    let z = a * f + b;
+                    z.max(0.0) + (-z.abs()).exp().ln_1p() - t * z
+                })
+                .sum()
+        };
+
+        let (mut a, mut b) = (0.0, ((positives + 1.0) / (negatives + 1.0)).ln());
+        let mut current = loss(a, b);
+        for _ in 0..100 {
+            // gradient and Hessian of the summed loss with respect to (a, b)
+            let (mut ga, mut gb, mut haa, mut hab, mut hbb) = (0.0, 0.0, 0.0, 0.0, 0.0);
+            for &(f, t) in &samples {
+                let q = MLPFunc::Logistic.function(a * f + b);
+                let w = q * (1.0 - q);
+                ga += (q - t) * f;
+                gb += q - t;
+                haa += w * f * f;
+                hab += w * f;
+                hbb += w;
+            }
+            // a little damping keeps the Hessian invertible
+            let (haa, hbb) = (haa + 1e-9, hbb + 1e-9);
+            let det = haa * hbb - hab * hab;
+            let da = (hbb * ga - hab * gb) / det;
+            let db = (haa * gb - hab * ga) / det;
+            // expected decrease of a full step, for the sufficient decrease condition
+            let decrease = ga * da + gb * db;
+
+            let mut step = 1.0;
+            while step >= 1e-10 {
+                let (next_a, next_b) = (a - step * da, b - step * db);
+                let next = loss(next_a, next_b);
+                if next < current - 1e-4 * step * decrease {
+                    (a, b, current) = (next_a, next_b, next);
+                    break;
+                }
+                step /= 2.0;
+            }
+            if step < 1e-10 || (step * da).abs() < 1e-10 && (step * db).abs() < 1e-10 {
+                break;
+            }
+        }
+        Calibration::Platt { a, b }
+    }
+
+    // Fits the non-decreasing function closest to the targets in squared error using
+    // pool adjacent violators
+    pub fn isotonic(probabilities: &[f64], targets: &[f64]) -> Self {
+        let mut order: Vec<usize> = (0..probabilities.len()).collect();
+        order.sort_by(|&i, &j| probabilities[i].total_cmp(&probabilities[j]));
+
+        // (sum of x, sum of y, count) per block; tied probabilities start in the same block
+        let mut blocks: Vec<(f64, f64, f64)> = vec![];
+        let mut previous = None;
+        for i in order {
+            let (x, y) = (probabilities[i], targets[i]);
+            if previous == Some(x) {
+                let last = blocks.last_mut().unwrap();
+                *last = (last.0 + x, last.1 + y, last.2 + 1.0);
+            } else {
+                blocks.push((x, y, 1.0));
+                previous = Some(x);
+            }
+            while blocks.len() > 1 {
+                let (x1, y1, n1) = blocks[blocks.len() - 1];
+                let (x0, y0, n0) = blocks[blocks.len() - 2];
+                if y0 / n0 <= y1 / n1 {
+                    break;
+                }
+                blocks.pop();
+                *blocks.last_mut().unwrap() = (x0 + x1, y0 + y1, n0 + n1);
+            }
+        }
+        if blocks.is_empty() {
+            return Calibration::None;
+        }
+        Calibration::Isotonic {
+            x: blocks.iter().map(|b| b.0 / b.2).collect(),
+            y: blocks.iter().map(|b| b.1 / b.2).collect(),
+        }
+    }
+}
+
+fn logit(p: f64) -> f64 {
+    let p = p.clamp(EPSILON, 1.0 - EPSILON);
+    (p / (1.0 - p)).ln()
+}
+
+// Samples whose predicted probability falls in [lower, upper)
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ReliabilityBin {
+    pub lower: f64,
+    pub upper: f64,
+    pub count: usize,
+    pub mean_predicted: f64,
+    pub observed_rate: f64,
+}
+
+// Equal-width bins over [0, 1] for a reliability diagram. Empty bins are left out
+pub fn reliability(probabilities: &[f64], targets: &[f64], bins: usize) -> Vec<ReliabilityBin> {
+    let mut sums = vec![(0.0, 0.0, 0); bins];
+    for (&p, &y) in probabilities.iter().zip(targets) {
+        let bin = ((p * bins as f64) as usize).min(bins - 1);
+        sums[bin].0 += p;
+        sums[bin].1 += if y > 0.5 { 1.0 } else { 0.0 };
+        sums[bin].2 += 1;
+    }
+    sums.into_iter()
+        .enumerate()
+        .filter(|(_, s)| s.2 > 0)
+        .map(|(i, (p, y, count))| ReliabilityBin {
+            lower: i as f64 / bins as f64,
+            upper: (i + 1) as f64 / bins as f64,
+            count,
+            mean_predicted: p / count as f64,
+            observed_rate: y / count as f64,
+        })
+        .collect()
+}
+
+// Count-weighted mean gap between predicted and observed rates over the bins
+pub fn expected_calibration_error(bins: &[ReliabilityBin]) -> f64 {
+    let total: usize = bins.iter().map(|b| b.count).sum();
+    if total == 0 {
+        return 0.0;
+    }
+    bins.iter()
+        .map(|b| b.count as f64 * (b.mean_predicted - b.observed_rate).abs())
+        .sum::<f64>()
+        / total as f64
+}
+
+#[cfg(test)]
+mod test {
+    use ndarray_rand::rand::{Rng, SeedableRng, rngs::StdRng};
+
+    use super::{Calibration, expected_calibration_error, reliability};
+
+    // Outputs that are systematically overconfident: the true rate is p^2
+    fn overconfident(n: usize) -> (Vec<f64>, Vec<f64>) {
+        let mut rng = StdRng::seed_from_u64(7);
+        (0..n)
+            .map(|_| {
+                let p: f64 = rng.r#gen();
+                (p, if rng.r#gen::<f64>() < p * p { 1.0 } else { 0.0 })
+            })
+            .unzip()
+    }
+
+    fn ece(calibration: &Calibration, p: &[f64], y: &[f64]) -> f64 {
+        let calibrated: Vec<f64> = p.iter().map(|&p| calibration.apply(p)).collect();
+        expected_calibration_error(&reliability(&calibrated, y, 10))
+    }
+
+    #[test]
+    fn test_isotonic_is_monotonic_and_pools_violators() {
+        let c = Calibration::isotonic(&[0.1, 0.2, 0.3, 0.4], &[0.0, 1.0, 0.0, 1.0]);
+        assert_eq!(
+            c,
+            Calibration::Isotonic {
+                x: vec![0.1, 0.25, 0.4],
+                y: vec![0.0, 0.5, 1.0],
+            }
+        );
+        assert_eq!(c.apply(0.0), 0.0);
+        assert_eq!(c.apply(0.25), 0.5);
+        assert!((c.apply(0.325) - 0.75).abs() < 1e-12);
+        assert_eq!(c.apply(0.9), 1.0);
+
+        let (p, y) = overconfident(2000);
+        let c = Calibration::isotonic(&p, &y);
+        let mut sorted = p.clone();
+        sorted.sort_by(f64::total_cmp);
+        assert!(sorted.windows(2).all(|w| c.apply(w[0]) <= c.apply(w[1])));
+    }
+
+    #[test]
+    fn test_calibration_reduces_expected_calibration_error() {
+        let (p, y) = overconfident(5000);
+        let before = ece(&Calibration::None, &p, &y);
+        assert!(before > 0.1, "{}", before);
+        for c in [Calibration::platt(&p, &y), Calibration::isotonic(&p, &y)] {
+            let after = ece(&c, &p, &y);
+            assert!(after < before / 3.0, "{:?}: {} vs {}", c, after, before);
+        }
+    }
+
+    #[test]
+    fn test_platt_recovers_a_known_sigmoid() {
+        // targets drawn from sigmoid(2 * logit(p) - 1)
+        let mut rng = StdRng::seed_from_u64(11);
+        let (p, y): (Vec<f64>, Vec<f64>) = (0..20000)
+            .map(|_| {
+                let p: f64 = rng.gen_range(0.01..0.99);
+                let q = 1.0 / (1.0 + (-(2.0 * (p / (1.0 - p)).ln() - 1.0)).exp());
+                (p, if rng.r#gen::<f64>() < q { 1.0 } else { 0.0 })
+            })
+            .unzip();
+        let Calibration::Platt { a, b } = Calibration::platt(&p, &y) else {
+            panic!("expected Platt scaling");
+        };
+        assert!((a - 2.0).abs() < 0.15, "a = {}", a);
+        assert!((b + 1.0).abs() < 0.15, "b = {}", b);
+    }
+
+    #[test]
+    fn test_platt_improves_saturated_overconfident_outputs() {
+        // outputs pinned at 0 or 1 that are wrong a quarter of the time
+        let p: Vec<f64> = (0..400).map(|i| (i % 2) as f64).collect();
+        let y: Vec<f64> = (0..400)
+            .map(|i| if i % 8 >= 6 { 1.0 - p[i] } else { p[i] })
+            .collect();
+        let log_loss = |c: &Calibration| -> f64 {
+            p.iter()
+                .zip(&y)
+                .map(|(&p, &y)| {
+                    let q = c.apply(p).clamp(1e-12, 1.0 - 1e-12);
+                    -(y * q.ln() + (1.0 - y) * (1.0 - q).ln())
+                })
+                .sum::<f64>()
+                / p.len() as f64
+        };
+        let c = Calibration::platt(&p, &y);
+        let Calibration::Platt { a, b } = c else {
+            panic!("expected Platt scaling");
+        };
+        assert!(a.is_finite() && b.is_finite() && a.abs() < 1.0, "{:?}", c);
+        assert!(log_loss(&c) <= log_loss(&Calibration::None));
+        assert!((c.apply(1.0) - 0.75).abs() < 0.01, "{}", c.apply(1.0));
+        assert!((c.apply(0.0) - 0.25).abs() < 0.01, "{}", c.apply(0.0));
+    }
+
+    #[test]
+    fn test_reliability_bins() {
+        let bins = reliability(&[0.05, 0.15, 0.12, 1.0], &[0.0, 1.0, 0.0, 1.0], 10);
+        assert_eq!(bins.len(), 3);
+        assert_eq!(bins[1].count, 2);
+        assert!((bins[1].mean_predicted - 0.135).abs() < 1e-12);
+        assert_eq!(bins[1].observed_rate, 0.5);
+        // 1.0 lands in the top bin
+        assert_eq!(bins[2].lower, 0.9);
+        let ece = expected_calibration_error(&bins);
+        assert!((ece - (0.05 + 2.0 * 0.365 + 0.0) / 4.0).abs() < 1e-12);
+    }
+}
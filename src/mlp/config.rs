@@ -1,26 +1,92 @@
-use std::default;
+use super::{
+    fns::{MLPFunc, OutputActivation},
+    init::Initializer,
+    loss::Loss,
+    optimizer::Optimizer,
+    schedule::Schedule,
+};
+use crate::data::idg::FeatureSpec;
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de::Error};
 
-use super::fns::{ActivationFnTypes, MLPFunc};
-use serde::{Deserialize, Deserializer, Serialize, Serializer};
+// Loss and accuracy of the model over a set of samples
+#[derive(Clone, Copy, Debug)]
+pub struct Evaluation {
+    pub loss: f64,
+    pub accuracy: f64,
+}
 
+// Passed to training observers at the end of every epoch
+#[derive(Clone, Copy, Debug)]
 pub struct TrainingState {
     pub total_epochs: u32,
     pub epoch: u32,
-    pub mse: f64,
+    // Every 1% of epochs, when progress is reported and early stopping is checked
+    pub reporting_interval: bool,
+    // Learning rate used for the epoch
+    pub learning_rate: f64,
+    // Mean loss over every training sample seen during the epoch
+    pub loss: f64,
+    // Accuracy over the training samples at the end of the epoch, present every reporting
+    // interval, or every epoch when an observer needs it
+    pub accuracy: Option<f64>,
+    // Present with the training accuracy when training with validation data
+    pub validation: Option<Evaluation>,
 }
 
+// Serialized through the derived impls below; Deserialize first fills in fields that
+// older model files lacked and that depend on other fields
 #[derive(Serialize, Deserialize)]
+#[serde(remote = "Self")]
 pub struct MLPConfig {
     pub layers: Vec<usize>,
+    // One activation per hidden layer; if fewer are given the last one is repeated.
+    // Older model files stored a single "activation" for every layer
     #[serde(
+        alias = "activation",
         serialize_with = "activation_serializer",
         deserialize_with = "activation_deserializer"
     )]
-    pub activation: MLPFunc,
+    pub activations: Vec<MLPFunc>,
+    // Applied to the output layer in place of the hidden activation. Older model files
+    // used the hidden activation for the output layer too
+    pub output_activation: OutputActivation,
     pub learning_rate: f64,
-    // Optional callback for training state updates
-    #[serde(skip)]
-    pub training_state_updated: Option<fn(TrainingState)>,
+    // Number of samples averaged into each weight update (1 = per-sample SGD)
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+    // Varies the learning rate by epoch
+    #[serde(default)]
+    pub schedule: Schedule,
+    // Epochs over which the learning rate ramps up linearly before the schedule starts
+    #[serde(default)]
+    pub warmup_epochs: u32,
+    #[serde(default)]
+    pub optimizer: Optimizer,
+    #[serde(default)]
+    pub loss: Loss,
+    // Seeds weight initialisation; MLP::new picks one at random when None
+    #[serde(default)]
+    pub seed: Option<u64>,
+    // Models saved before the initializer was configurable used Uniform
+    #[serde(default = "legacy_initializer")]
+    pub initializer: Initializer,
+    // Start biases at zero rather than Uniform(-0.5, 0.5)
+    #[serde(default)]
+    pub zero_biases: bool,
+    // L1 (lasso) and L2 (ridge) penalty coefficients on the weights, not the biases.
+    // The penalty l1 * sum(|w|) + l2 / 2 * sum(w^2) is added to the training loss
+    #[serde(default)]
+    pub l1: f64,
+    #[serde(default)]
+    pub l2: f64,
+    // Inverted dropout: probability of keeping each unit of a hidden layer during training,
+    // one per hidden layer with the last repeated. Empty (or 1.0) disables dropout
+    #[serde(default)]
+    pub keep_probabilities: Vec<f64>,
+    // Features the inputs are built from, which must be layers[0] wide; None for models
+    // trained on raw inputs. Older model files used the original three features
+    #[serde(default = "default_features")]
+    pub features: Option<FeatureSpec>,
 }
 
 impl Default for MLPConfig {
@@ -28,29 +94,226 @@ impl Default for MLPConfig {
         Self {
             // 2 inputs, 3 neurons in hidden layer and 1 output
             layers: vec![2, 3, 1],
-            activation: default::Default::default(),
+            activations: vec![MLPFunc::Logistic],
+            output_activation: OutputActivation::Sigmoid,
             learning_rate: 0.1,
-            training_state_updated: None,
+            batch_size: default_batch_size(),
+            schedule: Schedule::Constant,
+            warmup_epochs: 0,
+            optimizer: Optimizer::SGD,
+            loss: Loss::MSE,
+            seed: None,
+            initializer: Initializer::default(),
+            zero_biases: false,
+            l1: 0.0,
+            l2: 0.0,
+            keep_probabilities: vec![],
+            features: None,
+        }
+    }
+}
+
+impl MLPConfig {
+    // Activation for hidden layer l (0 = first hidden layer)
+    pub fn activation(&self, l: usize) -> MLPFunc {
+        self.activations
+            .get(l)
+            .or(self.activations.last())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    // Extra activations would be silently ignored, which usually means a layer is missing
+    pub fn check_activations(&self) -> Result<(), String> {
+        let hidden = self.layers.len().saturating_sub(2);
+        if self.activations.len() > hidden {
+            return Err(format!(
+                "{} activations given for {} hidden layers",
+                self.activations.len(),
+                hidden
+            ));
+        }
+        Ok(())
+    }
+
+    // Dropout keep probability for hidden layer l (0 = first hidden layer)
+    pub fn keep_probability(&self, l: usize) -> f64 {
+        self.keep_probabilities
+            .get(l)
+            .or(self.keep_probabilities.last())
+            .copied()
+            .unwrap_or(1.0)
+    }
+}
+
+impl Serialize for MLPConfig {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        MLPConfig::serialize(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for MLPConfig {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let mut json = serde_json::Value::deserialize(deserializer)?;
+        if let Some(config) = json.as_object_mut()
+            && !config.contains_key("output_activation")
+        {
+            let activations = config
+                .get("activations")
+                .or(config.get("activation"))
+                .ok_or(Error::missing_field("activations"))?;
+            let activations = activation_deserializer(activations).map_err(Error::custom)?;
+            let output_activation = legacy_output_activation(&activations)?;
+            config.insert(
+                "output_activation".to_string(),
+                serde_json::to_value(output_activation).map_err(Error::custom)?,
+            );
         }
+        MLPConfig::deserialize(json).map_err(Error::custom)
+    }
+}
+
+// The output activation a model saved before it was configurable really used
+fn legacy_output_activation<E: Error>(activations: &[MLPFunc]) -> Result<OutputActivation, E> {
+    match activations.last() {
+        Some(MLPFunc::Logistic) => Ok(OutputActivation::Sigmoid),
+        Some(MLPFunc::Tanh) => Ok(OutputActivation::Tanh),
+        Some(activation) => Err(E::custom(format!(
+            "missing output_activation, and {} has no output equivalent",
+            activation
+        ))),
+        None => Err(E::missing_field("output_activation")),
     }
 }
 
-fn activation_serializer<S>(activation: &MLPFunc, serializer: S) -> Result<S::Ok, S::Error>
+fn default_batch_size() -> usize {
+    1
+}
+
+fn legacy_initializer() -> Initializer {
+    Initializer::Uniform
+}
+
+fn default_features() -> Option<FeatureSpec> {
+    Some(FeatureSpec::default())
+}
+
+fn activation_serializer<S>(activations: &[MLPFunc], serializer: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
 {
-    serializer.serialize_u8(activation.fntype as u8)
+    serializer.collect_seq(activations.iter().map(|a| a.to_string()))
+}
+
+// Accepts a list of names, a single name, or the u8 codes written by older versions
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredActivation {
+    Code(u8),
+    Name(String),
+}
+
+impl StoredActivation {
+    fn into_func(self) -> Result<MLPFunc, String> {
+        match self {
+            StoredActivation::Code(code) => MLPFunc::try_from(code),
+            StoredActivation::Name(name) => name.parse(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredActivations {
+    One(StoredActivation),
+    Many(Vec<StoredActivation>),
 }
 
-fn activation_deserializer<'de, D>(deserializer: D) -> Result<MLPFunc, D::Error>
+fn activation_deserializer<'de, D>(deserializer: D) -> Result<Vec<MLPFunc>, D::Error>
 where
     D: Deserializer<'de>,
 {
-    let e: u8 = Deserialize::deserialize(deserializer)?;
-    ActivationFnTypes::try_from(e)
+    let stored = match StoredActivations::deserialize(deserializer)? {
+        StoredActivations::One(a) => vec![a],
+        StoredActivations::Many(a) => a,
+    };
+    stored
+        .into_iter()
+        .map(StoredActivation::into_func)
+        .collect::<Result<_, _>>()
         .map_err(serde::de::Error::custom)
-        .and_then(|fntype| match fntype {
-            ActivationFnTypes::Logistic => Ok(MLPFunc::default()),
-            ActivationFnTypes::Tanh => Ok(super::fns::TANH),
-        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::MLPConfig;
+    use crate::mlp::fns::{MLPFunc, OutputActivation};
+
+    #[test]
+    fn test_activations_are_stored_by_name() {
+        let config = MLPConfig {
+            layers: vec![3, 16, 16, 1],
+            activations: vec![MLPFunc::ReLU, MLPFunc::LeakyReLU(0.05)],
+            ..Default::default()
+        };
+        let json = serde_json::to_value(&config).unwrap();
+        assert_eq!(
+            json["activations"],
+            serde_json::json!(["relu", "leaky_relu(0.05)"])
+        );
+        let restored: MLPConfig = serde_json::from_value(json).unwrap();
+        assert_eq!(restored.activations, config.activations);
+    }
+
+    #[test]
+    fn test_legacy_activation_code_is_repeated_for_every_layer() {
+        let json = r#"{"layers":[3,4,4,1],"activation":1,"learning_rate":0.2}"#;
+        let config: MLPConfig = serde_json::from_str(json).unwrap();
+        assert_eq!(config.activation(0), MLPFunc::Tanh);
+        assert_eq!(config.activation(1), MLPFunc::Tanh);
+    }
+
+    #[test]
+    fn test_extra_activations_are_rejected() {
+        let config = MLPConfig {
+            layers: vec![3, 8, 1],
+            activations: vec![MLPFunc::ReLU],
+            ..Default::default()
+        };
+        assert!(config.check_activations().is_ok());
+        let config = MLPConfig {
+            activations: vec![MLPFunc::ReLU, MLPFunc::Tanh],
+            ..config
+        };
+        assert!(config.check_activations().is_err());
+    }
+
+    #[test]
+    fn test_legacy_output_activation_follows_hidden_activation() {
+        let json = r#"{"layers":[3,3,1],"activation":1,"learning_rate":0.2}"#;
+        let config: MLPConfig = serde_json::from_str(json).unwrap();
+        assert_eq!(config.output_activation, OutputActivation::Tanh);
+
+        let json = r#"{"layers":[3,3,1],"activation":0,"learning_rate":0.2}"#;
+        let config: MLPConfig = serde_json::from_str(json).unwrap();
+        assert_eq!(config.output_activation, OutputActivation::Sigmoid);
+
+        let json = r#"{"layers":[3,3,1],"activation":"relu","learning_rate":0.2}"#;
+        assert!(serde_json::from_str::<MLPConfig>(json).is_err());
+
+        let config = MLPConfig {
+            activations: vec![MLPFunc::ReLU],
+            output_activation: OutputActivation::Identity,
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&config).unwrap();
+        let restored: MLPConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.output_activation, OutputActivation::Identity);
+    }
 }
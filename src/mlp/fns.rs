@@ -1,63 +1,250 @@
-use std::f64::consts::E;
+use std::{f64::consts::E, fmt, str::FromStr};
 
-use ndarray::Array2;
+use ndarray::{Array2, Axis};
+use serde::{Deserialize, Serialize};
 
+// Normalises each column (sample) independently; subtracting the column max avoids overflow
 pub fn softmax(z: &Array2<f64>) -> Array2<f64> {
-    z.exp() / z.exp().sum()
+    let mut a = z.clone();
+    for mut col in a.axis_iter_mut(Axis(1)) {
+        let max = col.fold(f64::NEG_INFINITY, |m, v| m.max(*v));
+        col.mapv_inplace(|v| (v - max).exp());
+        let sum = col.sum();
+        col /= sum;
+    }
+    a
 }
 
 pub fn sigmoid(z: &Array2<f64>) -> Array2<f64> {
-    z.mapv(|v| (LOGISTIC.function)(&v))
+    z.mapv(logistic)
+}
+
+// Activation applied to the output layer, which may be non-elementwise (softmax)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputActivation {
+    Identity,
+    #[default]
+    #[serde(alias = "logistic")]
+    Sigmoid,
+    Softmax,
+    Tanh,
+}
+
+impl OutputActivation {
+    pub fn apply(&self, z: &Array2<f64>) -> Array2<f64> {
+        match self {
+            OutputActivation::Identity => z.clone(),
+            OutputActivation::Sigmoid => sigmoid(z),
+            OutputActivation::Softmax => softmax(z),
+            OutputActivation::Tanh => z.mapv(f64::tanh),
+        }
+    }
+
+    // Converts dC/da into dC/dz given the layer's output a = f(z)
+    pub fn backward(&self, a: &Array2<f64>, grad: &Array2<f64>) -> Array2<f64> {
+        match self {
+            OutputActivation::Identity => grad.clone(),
+            OutputActivation::Sigmoid => grad * &a.mapv(|a| a * (1.0 - a)),
+            OutputActivation::Tanh => grad * &a.mapv(|a| 1.0 - a * a),
+            OutputActivation::Softmax => {
+                // Jacobian-vector product per column: a_i * (g_i - sum_j g_j * a_j)
+                let dot = (grad * a).sum_axis(Axis(0)).insert_axis(Axis(0));
+                a * &(grad - &dot)
+            }
+        }
+    }
+}
+
+impl fmt::Display for OutputActivation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            OutputActivation::Identity => "identity",
+            OutputActivation::Sigmoid => "sigmoid",
+            OutputActivation::Softmax => "softmax",
+            OutputActivation::Tanh => "tanh",
+        };
+        write!(f, "{}", name)
+    }
 }
-#[derive(Clone, Copy, Debug)]
-#[repr(u8)]
-pub enum ActivationFnTypes {
+
+impl FromStr for OutputActivation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "identity" => Ok(OutputActivation::Identity),
+            "sigmoid" | "logistic" => Ok(OutputActivation::Sigmoid),
+            "softmax" => Ok(OutputActivation::Softmax),
+            "tanh" => Ok(OutputActivation::Tanh),
+            _ => Err(format!("Invalid output activation: {}", s)),
+        }
+    }
+}
+
+// Elementwise activation used by the hidden layers
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum MLPFunc {
+    #[default]
     Logistic,
     Tanh,
+    ReLU,
+    // slope alpha for x < 0
+    LeakyReLU(f64),
+    // saturates at -alpha for large negative x
+    ELU(f64),
+    // tanh approximation from Hendrycks & Gimpel, https://arxiv.org/abs/1606.08415
+    GELU,
+    Softplus,
+    // x * sigmoid(x), a.k.a. SiLU
+    Swish,
+}
+
+// sqrt(2 / pi)
+const GELU_C: f64 = 0.7978845608028654;
+
+fn logistic(x: f64) -> f64 {
+    1.0 / (1.0 + E.powf(-x))
 }
 
-impl std::convert::TryFrom<u8> for ActivationFnTypes {
+impl MLPFunc {
+    pub fn function(&self, x: f64) -> f64 {
+        match *self {
+            MLPFunc::Logistic => logistic(x),
+            MLPFunc::Tanh => x.tanh(),
+            MLPFunc::ReLU => x.max(0.0),
+            MLPFunc::LeakyReLU(alpha) => {
+                if x > 0.0 {
+                    x
+                } else {
+                    alpha * x
+                }
+            }
+            MLPFunc::ELU(alpha) => {
+                if x > 0.0 {
+                    x
+                } else {
+                    alpha * x.exp_m1()
+                }
+            }
+            MLPFunc::GELU => 0.5 * x * (1.0 + (GELU_C * (x + 0.044715 * x.powi(3))).tanh()),
+            // ln(1 + e^x), rearranged so large |x| doesn't overflow
+            MLPFunc::Softplus => x.max(0.0) + (-x.abs()).exp().ln_1p(),
+            MLPFunc::Swish => x * logistic(x),
+        }
+    }
+
+    pub fn derivative(&self, x: f64) -> f64 {
+        match *self {
+            MLPFunc::Logistic => logistic(x) * (1.0 - logistic(x)),
+            MLPFunc::Tanh => 1.0 - (x.tanh()).powi(2),
+            MLPFunc::ReLU => {
+                if x > 0.0 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            MLPFunc::LeakyReLU(alpha) => {
+                if x > 0.0 {
+                    1.0
+                } else {
+                    alpha
+                }
+            }
+            MLPFunc::ELU(alpha) => {
+                if x > 0.0 {
+                    1.0
+                } else {
+                    alpha * x.exp()
+                }
+            }
+            MLPFunc::GELU => {
+                let u = GELU_C * (x + 0.044715 * x.powi(3));
+                let t = u.tanh();
+                let du = GELU_C * (1.0 + 3.0 * 0.044715 * x * x);
+                0.5 * (1.0 + t) + 0.5 * x * (1.0 - t * t) * du
+            }
+            MLPFunc::Softplus => logistic(x),
+            MLPFunc::Swish => {
+                let s = logistic(x);
+                s + x * s * (1.0 - s)
+            }
+        }
+    }
+}
+
+// Models saved before activations were stored by name used 0 = logistic, 1 = tanh
+impl std::convert::TryFrom<u8> for MLPFunc {
     type Error = String;
 
     fn try_from(value: u8) -> Result<Self, Self::Error> {
         match value {
-            0 => Ok(ActivationFnTypes::Logistic),
-            1 => Ok(ActivationFnTypes::Tanh),
+            0 => Ok(MLPFunc::Logistic),
+            1 => Ok(MLPFunc::Tanh),
             _ => Err(format!("Invalid activation function type: {}", value)),
         }
     }
 }
 
-#[derive(Clone, Copy, Debug)]
-pub struct MLPFunc {
-    pub fntype: ActivationFnTypes,
-    pub function: fn(&f64) -> f64,
-    pub derivative: fn(&f64) -> f64,
-}
-
-impl Default for MLPFunc {
-    fn default() -> Self {
-        LOGISTIC
+// Readable names, e.g. "relu" or "leaky_relu(0.01)", used by both the CLI and saved models
+impl fmt::Display for MLPFunc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MLPFunc::Logistic => write!(f, "logistic"),
+            MLPFunc::Tanh => write!(f, "tanh"),
+            MLPFunc::ReLU => write!(f, "relu"),
+            MLPFunc::LeakyReLU(alpha) => write!(f, "leaky_relu({})", alpha),
+            MLPFunc::ELU(alpha) => write!(f, "elu({})", alpha),
+            MLPFunc::GELU => write!(f, "gelu"),
+            MLPFunc::Softplus => write!(f, "softplus"),
+            MLPFunc::Swish => write!(f, "swish"),
+        }
     }
 }
 
-pub const LOGISTIC: MLPFunc = MLPFunc {
-    fntype: ActivationFnTypes::Logistic,
-    function: |x| 1.0 / (1.0 + E.powf(-x)),
-    derivative: |x| (LOGISTIC.function)(x) * (1.0 - (LOGISTIC.function)(x)),
-};
+impl FromStr for MLPFunc {
+    type Err = String;
 
-pub const TANH: MLPFunc = MLPFunc {
-    fntype: ActivationFnTypes::Tanh,
-    function: |x| x.tanh(),
-    derivative: |x| 1.0 - (x.tanh()).powi(2),
-};
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Split "name(param)" into its name and optional parameter
+        let (name, param) = match s.trim().split_once('(') {
+            Some((name, rest)) => {
+                let param = rest
+                    .strip_suffix(')')
+                    .ok_or_else(|| format!("Missing ')' in activation: {}", s))?;
+                let param = param
+                    .trim()
+                    .parse::<f64>()
+                    .map_err(|e| format!("Invalid activation parameter in {}: {}", s, e))?;
+                (name.trim(), Some(param))
+            }
+            None => (s.trim(), None),
+        };
+        let no_param = |f: MLPFunc| match param {
+            None => Ok(f),
+            Some(_) => Err(format!("Activation {} does not take a parameter", name)),
+        };
+        match name {
+            "logistic" | "sigmoid" => no_param(MLPFunc::Logistic),
+            "tanh" => no_param(MLPFunc::Tanh),
+            "relu" => no_param(MLPFunc::ReLU),
+            "leaky_relu" => Ok(MLPFunc::LeakyReLU(param.unwrap_or(0.01))),
+            "elu" => Ok(MLPFunc::ELU(param.unwrap_or(1.0))),
+            "gelu" => no_param(MLPFunc::GELU),
+            "softplus" => no_param(MLPFunc::Softplus),
+            "swish" => no_param(MLPFunc::Swish),
+            _ => Err(format!("Invalid activation function: {}", s)),
+        }
+    }
+}
 
 #[cfg(test)]
 mod test {
     use ndarray::Array;
 
-    use super::softmax;
+    use super::{MLPFunc, OutputActivation, softmax};
 
     #[test]
     fn test_softmax() {
@@ -70,4 +257,87 @@ mod test {
             assert!((result[[i, 0]] - expected[[i, 0]]).abs() < 1e-6);
         }
     }
+
+    #[test]
+    fn test_softmax_normalises_each_column() {
+        let z = Array::from_shape_vec((2, 2), vec![1.0, 1000.0, 2.0, 1000.0]).unwrap();
+        let result = softmax(&z);
+        assert!((result[[0, 0]] - 0.26894142).abs() < 1e-6);
+        assert!((result[[0, 1]] - 0.5).abs() < 1e-12);
+        assert!((result[[1, 1]] - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_softmax_backward_matches_jacobian() {
+        let z = Array::from_shape_vec((3, 1), vec![0.3, -1.0, 2.0]).unwrap();
+        let g = Array::from_shape_vec((3, 1), vec![1.0, -2.0, 0.5]).unwrap();
+        let a = softmax(&z);
+        let delta = OutputActivation::Softmax.backward(&a, &g);
+        for i in 0..3 {
+            // dC/dz_i = sum_j g_j * a_j * (kronecker(i, j) - a_i)
+            let expected: f64 = (0..3)
+                .map(|j| g[[j, 0]] * a[[j, 0]] * ((i == j) as u8 as f64 - a[[i, 0]]))
+                .sum();
+            assert!((delta[[i, 0]] - expected).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_derivatives_match_finite_differences() {
+        let fns = [
+            MLPFunc::Logistic,
+            MLPFunc::Tanh,
+            MLPFunc::ReLU,
+            MLPFunc::LeakyReLU(0.1),
+            MLPFunc::ELU(1.5),
+            MLPFunc::GELU,
+            MLPFunc::Softplus,
+            MLPFunc::Swish,
+        ];
+        let h = 1e-6;
+        for f in fns {
+            // avoid x = 0 where the ReLU family has a kink
+            for x in [-3.0, -0.7, 0.4, 2.5] {
+                let numeric = (f.function(x + h) - f.function(x - h)) / (2.0 * h);
+                assert!(
+                    (f.derivative(x) - numeric).abs() < 1e-6,
+                    "{} derivative at {}",
+                    f,
+                    x
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_activation_names_round_trip() {
+        for f in [
+            MLPFunc::Logistic,
+            MLPFunc::Tanh,
+            MLPFunc::ReLU,
+            MLPFunc::LeakyReLU(0.2),
+            MLPFunc::ELU(1.0),
+            MLPFunc::GELU,
+            MLPFunc::Softplus,
+            MLPFunc::Swish,
+        ] {
+            assert_eq!(f.to_string().parse::<MLPFunc>(), Ok(f));
+        }
+        assert_eq!(
+            "leaky_relu".parse::<MLPFunc>(),
+            Ok(MLPFunc::LeakyReLU(0.01))
+        );
+        assert!("relu(2)".parse::<MLPFunc>().is_err());
+        assert!("leaky_relu(x)".parse::<MLPFunc>().is_err());
+        assert_eq!(
+            "logistic".parse::<OutputActivation>(),
+            Ok(OutputActivation::Sigmoid)
+        );
+    }
+
+    #[test]
+    fn test_softplus_does_not_overflow() {
+        assert_eq!(MLPFunc::Softplus.function(1000.0), 1000.0);
+        assert_eq!(MLPFunc::Softplus.function(-1000.0), 0.0);
+    }
 }
@@ -0,0 +1,294 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    time::Instant,
+};
+
+use chrono::{DateTime, Utc};
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    config::TrainingState,
+    mlp::MLP,
+    observer::{Control, TrainingObserver},
+};
+
+// Metrics for one epoch. Epochs are counted across resumed runs
+#[derive(Clone, Debug, PartialEq)]
+pub struct HistoryRow {
+    pub epoch: u32,
+    pub learning_rate: f64,
+    pub loss: f64,
+    // Training accuracy
+    pub accuracy: Option<f64>,
+    pub validation_loss: Option<f64>,
+    pub validation_accuracy: Option<f64>,
+    // Wall-clock time since the start of the run
+    pub elapsed_seconds: f64,
+}
+
+// Summary of a training run, stored in the model file
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HistorySummary {
+    pub finished_at: DateTime<Utc>,
+    pub epochs: u32,
+    pub final_loss: f64,
+    pub best_validation_loss: Option<f64>,
+    pub best_validation_epoch: Option<u32>,
+    pub final_validation_accuracy: Option<f64>,
+    pub elapsed_seconds: f64,
+}
+
+// Records every epoch and adds a summary to the model when training ends. A History
+// may be reused across runs, keeping every row but summarising each run separately
+#[derive(Default)]
+pub struct History {
+    pub rows: Vec<HistoryRow>,
+    // Index of the first row of the current run, and when that run started
+    run_start: usize,
+    start: Option<Instant>,
+    // The file rows are recorded to, and its writer while rows are streamed as CSV
+    path: Option<String>,
+    csv: Option<BufWriter<File>>,
+}
+
+impl History {
+    // Also records to a file, with validation metrics at every epoch. CSV rows are written
+    // as each epoch ends so an interrupted run keeps its history; parquet is written when
+    // training ends
+    pub fn recording_to(path: &str) -> io::Result<Self> {
+        let csv = if path.ends_with(".parquet") {
+            None
+        } else {
+            let mut csv = BufWriter::new(File::create(path)?);
+            writeln!(
+                csv,
+                "epoch,learning_rate,loss,accuracy,validation_loss,validation_accuracy,elapsed_seconds"
+            )?;
+            csv.flush()?;
+            Some(csv)
+        };
+        Ok(Self {
+            path: Some(path.to_string()),
+            csv,
+            ..Default::default()
+        })
+    }
+
+    // Summary of the latest run
+    pub fn summary(&self) -> Option<HistorySummary> {
+        let run = &self.rows[self.run_start..];
+        let (first, last) = (run.first()?, run.last()?);
+        let best = run
+            .iter()
+            .filter_map(|r| r.validation_loss.map(|loss| (loss, r.epoch)))
+            .min_by(|a, b| a.0.total_cmp(&b.0));
+        Some(HistorySummary {
+            finished_at: Utc::now(),
+            epochs: last.epoch - first.epoch + 1,
+            final_loss: last.loss,
+            best_validation_loss: best.map(|(loss, _)| loss),
+            best_validation_epoch: best.map(|(_, epoch)| epoch),
+            final_validation_accuracy: last.validation_accuracy,
+            elapsed_seconds: last.elapsed_seconds,
+        })
+    }
+
+    pub fn to_dataframe(&self) -> PolarsResult<DataFrame> {
+        df!(
+            "epoch" => self.rows.iter().map(|r| r.epoch).collect::<Vec<_>>(),
+            "learning_rate" => self.rows.iter().map(|r| r.learning_rate).collect::<Vec<_>>(),
+            "loss" => self.rows.iter().map(|r| r.loss).collect::<Vec<_>>(),
+            "accuracy" => self.rows.iter().map(|r| r.accuracy).collect::<Vec<_>>(),
+            "validation_loss" => self.rows.iter().map(|r| r.validation_loss).collect::<Vec<_>>(),
+            "validation_accuracy" => self.rows.iter().map(|r| r.validation_accuracy).collect::<Vec<_>>(),
+            "elapsed_seconds" => self.rows.iter().map(|r| r.elapsed_seconds).collect::<Vec<_>>()
+        )
+    }
+
+    // Writes parquet for a .parquet extension, otherwise CSV
+    pub fn write(&self, path: &str) -> PolarsResult<()> {
+        let mut df = self.to_dataframe()?;
+        let mut file = File::create(path)?;
+        if path.ends_with(".parquet") {
+            ParquetWriter::new(&mut file).finish(&mut df)?;
+        } else {
+            CsvWriter::new(&mut file).finish(&mut df)?;
+        }
+        Ok(())
+    }
+
+    fn stream(csv: &mut BufWriter<File>, row: &HistoryRow) -> io::Result<()> {
+        let cell = |value: Option<f64>| value.map_or(String::new(), |v| v.to_string());
+        writeln!(
+            csv,
+            "{},{},{},{},{},{},{}",
+            row.epoch,
+            row.learning_rate,
+            row.loss,
+            cell(row.accuracy),
+            cell(row.validation_loss),
+            cell(row.validation_accuracy),
+            row.elapsed_seconds
+        )?;
+        csv.flush()
+    }
+}
+
+impl TrainingObserver for History {
+    fn on_epoch_start(&mut self, epoch: u32, _learning_rate: f64) {
+        // epochs are numbered from 1 within each run
+        if epoch == 1 {
+            self.run_start = self.rows.len();
+            self.start = Some(Instant::now());
+        }
+    }
+
+    fn needs_every_epoch(&self) -> bool {
+        self.path.is_some()
+    }
+
+    fn on_epoch_end(&mut self, mlp: &MLP, ts: &TrainingState) -> Control {
+        let row = HistoryRow {
+            epoch: mlp.epochs_trained(),
+            learning_rate: ts.learning_rate,
+            loss: ts.loss,
+            accuracy: ts.accuracy,
+            validation_loss: ts.validation.map(|v| v.loss),
+            validation_accuracy: ts.validation.map(|v| v.accuracy),
+            elapsed_seconds: self.start.map_or(0.0, |s| s.elapsed().as_secs_f64()),
+        };
+        if let Some(csv) = self.csv.as_mut()
+            && let Err(e) = Self::stream(csv, &row)
+        {
+            eprintln!("Failed to write history: {}", e);
+            self.csv = None;
+        }
+        self.rows.push(row);
+        Control::Continue
+    }
+
+    fn on_training_end(&mut self, mlp: &mut MLP, _epochs_run: u32) {
+        if let Some(summary) = self.summary() {
+            mlp.add_history(summary);
+        }
+        if let Some(path) = self.path.as_deref().filter(|p| p.ends_with(".parquet"))
+            && let Err(e) = self.write(path)
+        {
+            eprintln!("Failed to write history: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use polars::prelude::*;
+
+    use super::History;
+    use crate::mlp::{config::MLPConfig, mlp::MLP};
+
+    fn trained_history() -> (MLP, History) {
+        let inputs = vec![vec![0.0, 1.0], vec![1.0, 1.0]];
+        let targets = vec![vec![1.0], vec![0.0]];
+        let mut mlp = MLP::new(MLPConfig::default());
+        let mut history = History::default();
+        mlp.train_with_observers(
+            inputs.clone(),
+            targets.clone(),
+            Some((&inputs, &targets)),
+            4,
+            &mut [&mut history],
+        );
+        (mlp, history)
+    }
+
+    #[test]
+    fn test_history_records_every_epoch_and_summarises_into_the_model() {
+        let (mut mlp, mut history) = trained_history();
+        assert_eq!(history.rows.len(), 4);
+        assert_eq!(history.rows[3].epoch, 4);
+        assert!(
+            history
+                .rows
+                .windows(2)
+                .all(|w| w[0].elapsed_seconds <= w[1].elapsed_seconds)
+        );
+
+        let summary = history.summary().unwrap();
+        let best = history
+            .rows
+            .iter()
+            .map(|r| r.validation_loss.unwrap())
+            .fold(f64::INFINITY, f64::min);
+        assert_eq!(summary.best_validation_loss, Some(best));
+        assert_eq!(mlp.history().len(), 1);
+
+        // A resumed run appends another summary, continuing the epoch count
+        mlp.train_with_observers(
+            vec![vec![0.0, 1.0]],
+            vec![vec![1.0]],
+            None,
+            2,
+            &mut [&mut history],
+        );
+        assert_eq!(history.rows.last().unwrap().epoch, 6);
+        assert_eq!(mlp.history().len(), 2);
+        assert_eq!(mlp.history()[1].epochs, 2);
+        assert_eq!(mlp.history()[1].best_validation_loss, None);
+        let json = serde_json::to_string(&mlp).unwrap();
+        let restored: MLP = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.history(), mlp.history());
+    }
+
+    #[test]
+    fn test_history_is_written_as_csv_or_parquet() {
+        let (_, history) = trained_history();
+        for extension in ["csv", "parquet"] {
+            let path = std::env::temp_dir().join(format!("hueml-history-test.{}", extension));
+            let path = path.to_str().unwrap().to_string();
+            history.write(&path).unwrap();
+
+            let mut file = std::fs::File::open(&path).unwrap();
+            let df = if extension == "csv" {
+                CsvReader::new(&mut file).finish().unwrap()
+            } else {
+                ParquetReader::new(&mut file).finish().unwrap()
+            };
+            assert_eq!(df.shape(), (4, 7));
+            assert_eq!(
+                df.column("loss").unwrap().f64().unwrap().get(3),
+                Some(history.rows[3].loss)
+            );
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_recorded_history_streams_metrics_for_every_epoch() {
+        let path = std::env::temp_dir().join("hueml-history-stream-test.csv");
+        let path = path.to_str().unwrap().to_string();
+        let inputs = vec![vec![0.0, 1.0], vec![1.0, 1.0]];
+        let targets = vec![vec![1.0], vec![0.0]];
+        let mut mlp = MLP::new(MLPConfig::default());
+        let mut history = History::recording_to(&path).unwrap();
+
+        // Progress is only reported every other epoch over 200 epochs, but a recorded
+        // history still has validation metrics and training accuracy for every epoch
+        mlp.train_with_observers(
+            inputs.clone(),
+            targets.clone(),
+            Some((&inputs, &targets)),
+            200,
+            &mut [&mut history],
+        );
+        let df = CsvReader::new(std::fs::File::open(&path).unwrap())
+            .finish()
+            .unwrap();
+        assert_eq!(df.shape(), (200, 7));
+        for column in ["accuracy", "validation_loss", "validation_accuracy"] {
+            assert_eq!(df.column(column).unwrap().null_count(), 0);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,163 @@
+use std::{fmt, str::FromStr};
+
+use ndarray::Array2;
+use ndarray_rand::RandomExt;
+use ndarray_rand::rand::Rng;
+use ndarray_rand::rand_distr::{Normal, StandardNormal, Uniform};
+use serde::{Deserialize, Serialize};
+
+// Understanding the difficulty of training deep feedforward neural networks (Glorot & Bengio)
+// https://proceedings.mlr.press/v9/glorot10a.html
+// Delving Deep into Rectifiers (He et al.)
+// https://arxiv.org/abs/1502.01852
+
+// Weight initialisation scheme. fan_in and fan_out are the sizes of the
+// layers either side of the weight matrix
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Initializer {
+    // Uniform(-0.5, 0.5) whatever the layer size
+    Uniform,
+    // Uniform(-l, l), l = sqrt(6 / (fan_in + fan_out)); suits tanh and logistic
+    #[default]
+    XavierUniform,
+    // Normal(0, sqrt(2 / (fan_in + fan_out)))
+    XavierNormal,
+    // Uniform(-l, l), l = sqrt(6 / fan_in); suits the ReLU family
+    HeUniform,
+    // Normal(0, sqrt(2 / fan_in))
+    HeNormal,
+    // Uniform(-l, l), l = sqrt(3 / fan_in)
+    LeCunUniform,
+    // Normal(0, sqrt(1 / fan_in)); suits SELU-like activations
+    LeCunNormal,
+    // Orthonormal rows or columns from Gram-Schmidt on a Gaussian matrix (Saxe et al.)
+    Orthogonal,
+}
+
+const NAMES: [(Initializer, &str); 8] = [
+    (Initializer::Uniform, "uniform"),
+    (Initializer::XavierUniform, "xavier_uniform"),
+    (Initializer::XavierNormal, "xavier_normal"),
+    (Initializer::HeUniform, "he_uniform"),
+    (Initializer::HeNormal, "he_normal"),
+    (Initializer::LeCunUniform, "lecun_uniform"),
+    (Initializer::LeCunNormal, "lecun_normal"),
+    (Initializer::Orthogonal, "orthogonal"),
+];
+
+impl Initializer {
+    // A (fan_out, fan_in) weight matrix
+    pub fn weights<R: Rng>(&self, fan_out: usize, fan_in: usize, rng: &mut R) -> Array2<f64> {
+        let shape = (fan_out, fan_in);
+        let (n_in, n_out) = (fan_in as f64, fan_out as f64);
+        let uniform = |limit: f64, rng: &mut R| {
+            Array2::random_using(shape, Uniform::new_inclusive(-limit, limit), rng)
+        };
+        let normal = |std_dev: f64, rng: &mut R| {
+            Array2::random_using(shape, Normal::new(0.0, std_dev).unwrap(), rng)
+        };
+        match self {
+            Initializer::Uniform => Array2::random_using(shape, Uniform::new(-0.5, 0.5), rng),
+            Initializer::XavierUniform => uniform((6.0 / (n_in + n_out)).sqrt(), rng),
+            Initializer::XavierNormal => normal((2.0 / (n_in + n_out)).sqrt(), rng),
+            Initializer::HeUniform => uniform((6.0 / n_in).sqrt(), rng),
+            Initializer::HeNormal => normal((2.0 / n_in).sqrt(), rng),
+            Initializer::LeCunUniform => uniform((3.0 / n_in).sqrt(), rng),
+            Initializer::LeCunNormal => normal((1.0 / n_in).sqrt(), rng),
+            Initializer::Orthogonal => orthogonal(fan_out, fan_in, rng),
+        }
+    }
+}
+
+fn orthogonal<R: Rng>(rows: usize, cols: usize, rng: &mut R) -> Array2<f64> {
+    // Orthonormalise the columns of a tall Gaussian matrix using modified Gram-Schmidt
+    let (n, k) = (rows.max(cols), rows.min(cols));
+    let mut q: Array2<f64> = Array2::random_using((n, k), StandardNormal, rng);
+    for j in 0..k {
+        for i in 0..j {
+            let qi = q.column(i).to_owned();
+            let proj = qi.dot(&q.column(j));
+            q.column_mut(j).scaled_add(-proj, &qi);
+        }
+        let norm = q.column(j).dot(&q.column(j)).sqrt();
+        q.column_mut(j).mapv_inplace(|v| v / norm);
+    }
+    // A wide matrix gets orthonormal rows instead
+    if rows < cols { q.t().to_owned() } else { q }
+}
+
+impl fmt::Display for Initializer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (_, name) = NAMES.iter().find(|(i, _)| i == self).unwrap();
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Initializer {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        NAMES
+            .iter()
+            .find(|(_, name)| *name == s)
+            .map(|(i, _)| *i)
+            .ok_or_else(|| format!("Invalid initializer: {}", s))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ndarray::Array2;
+    use ndarray_rand::rand::{SeedableRng, rngs::StdRng};
+
+    use super::Initializer;
+
+    #[test]
+    fn test_orthogonal_is_orthonormal() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for (rows, cols) in [(8, 3), (3, 8), (5, 5)] {
+            let w = Initializer::Orthogonal.weights(rows, cols, &mut rng);
+            assert_eq!(w.dim(), (rows, cols));
+            // W^T W = I for tall matrices, W W^T = I for wide ones
+            let gram = if rows >= cols {
+                w.t().dot(&w)
+            } else {
+                w.dot(&w.t())
+            };
+            let identity = Array2::<f64>::eye(rows.min(cols));
+            assert!((gram - identity).iter().all(|v| v.abs() < 1e-10));
+        }
+    }
+
+    #[test]
+    fn test_uniform_schemes_respect_their_limits() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let xavier = Initializer::XavierUniform.weights(32, 32, &mut rng);
+        assert!(xavier.iter().all(|v| v.abs() <= (6.0f64 / 64.0).sqrt()));
+        let he = Initializer::HeUniform.weights(32, 6, &mut rng);
+        assert!(he.iter().all(|v| v.abs() <= 1.0));
+    }
+
+    #[test]
+    fn test_he_normal_variance() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let w = Initializer::HeNormal.weights(400, 50, &mut rng);
+        let variance = w.mapv(|v| v * v).mean().unwrap();
+        assert!((variance - 2.0 / 50.0).abs() < 0.004);
+    }
+
+    #[test]
+    fn test_names_round_trip() {
+        for name in [
+            "uniform",
+            "xavier_normal",
+            "he_uniform",
+            "lecun_normal",
+            "orthogonal",
+        ] {
+            assert_eq!(name.parse::<Initializer>().unwrap().to_string(), name);
+        }
+        assert!("glorot".parse::<Initializer>().is_err());
+    }
+}
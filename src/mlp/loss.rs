@@ -0,0 +1,129 @@
+use ndarray::Array2;
+use serde::{Deserialize, Serialize};
+
+// Predictions are clamped to [EPSILON, 1 - EPSILON] before taking logs
+const EPSILON: f64 = 1e-12;
+
+// Loss functions compare the network output a with the target y.
+// Both are (n_outputs, batch) matrices; per-sample losses are summed over
+// the outputs and averaged over the batch columns.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+#[allow(clippy::upper_case_acronyms)]
+pub enum Loss {
+    // Quadratic cost 1/2 * (a - y)^2, as in http://neuralnetworksanddeeplearning.com/chap2.html
+    #[default]
+    #[serde(rename = "mse")]
+    MSE,
+    // -(y * ln(a) + (1 - y) * ln(1 - a)) for independent 0/1 targets
+    BinaryCrossEntropy,
+    // -y * ln(a) for one-hot targets
+    CategoricalCrossEntropy,
+    // Quadratic for |a - y| <= delta and linear beyond
+    Huber {
+        delta: f64,
+    },
+}
+
+impl Loss {
+    // Mean loss over the batch
+    pub fn loss(&self, a: &Array2<f64>, y: &Array2<f64>) -> f64 {
+        let batch = a.ncols() as f64;
+        let total: f64 = match *self {
+            Loss::MSE => a.iter().zip(y).map(|(a, y)| 0.5 * (a - y).powi(2)).sum(),
+            Loss::BinaryCrossEntropy => a
+                .iter()
+                .zip(y)
+                .map(|(a, y)| {
+                    let a = clamp(*a);
+                    -(y * a.ln() + (1.0 - y) * (1.0 - a).ln())
+                })
+                .sum(),
+            Loss::CategoricalCrossEntropy => {
+                a.iter().zip(y).map(|(a, y)| -y * clamp(*a).ln()).sum()
+            }
+            Loss::Huber { delta } => a
+                .iter()
+                .zip(y)
+                .map(|(a, y)| {
+                    let r = (a - y).abs();
+                    if r <= delta {
+                        0.5 * r * r
+                    } else {
+                        delta * (r - 0.5 * delta)
+                    }
+                })
+                .sum(),
+        };
+        total / batch
+    }
+
+    // dL/da for each sample (not averaged over the batch)
+    pub fn gradient(&self, a: &Array2<f64>, y: &Array2<f64>) -> Array2<f64> {
+        match *self {
+            Loss::MSE => a - y,
+            Loss::BinaryCrossEntropy => {
+                let mut grad = a - y;
+                grad.zip_mut_with(a, |g, a| {
+                    let a = clamp(*a);
+                    *g /= a * (1.0 - a);
+                });
+                grad
+            }
+            Loss::CategoricalCrossEntropy => {
+                let mut grad = -y;
+                grad.zip_mut_with(a, |g, a| *g /= clamp(*a));
+                grad
+            }
+            Loss::Huber { delta } => (a - y).mapv(|r| r.clamp(-delta, delta)),
+        }
+    }
+}
+
+fn clamp(a: f64) -> f64 {
+    a.clamp(EPSILON, 1.0 - EPSILON)
+}
+
+#[cfg(test)]
+mod test {
+    use ndarray::array;
+
+    use super::Loss;
+
+    #[test]
+    fn test_losses_are_averaged_over_the_batch() {
+        let a = array![[0.8, 0.4]];
+        let y = array![[1.0, 0.0]];
+        assert!((Loss::MSE.loss(&a, &y) - (0.02 + 0.08) / 2.0).abs() < 1e-12);
+        let bce = -(0.8f64.ln() + 0.6f64.ln()) / 2.0;
+        assert!((Loss::BinaryCrossEntropy.loss(&a, &y) - bce).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_categorical_cross_entropy_only_counts_the_target_class() {
+        let a = array![[0.7], [0.2], [0.1]];
+        let y = array![[0.0], [1.0], [0.0]];
+        assert!((Loss::CategoricalCrossEntropy.loss(&a, &y) + 0.2f64.ln()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_huber_is_linear_beyond_delta() {
+        let huber = Loss::Huber { delta: 1.0 };
+        let a = array![[0.5, 3.0]];
+        let y = array![[0.0, 0.0]];
+        assert!((huber.loss(&a, &y) - (0.125 + 2.5) / 2.0).abs() < 1e-12);
+        assert_eq!(huber.gradient(&a, &y), array![[0.5, 1.0]]);
+    }
+
+    #[test]
+    fn test_binary_cross_entropy_gradient_is_finite_at_the_bounds() {
+        let a = array![[0.0, 1.0]];
+        let y = array![[1.0, 0.0]];
+        assert!(
+            Loss::BinaryCrossEntropy
+                .gradient(&a, &y)
+                .iter()
+                .all(|g| g.is_finite())
+        );
+    }
+}
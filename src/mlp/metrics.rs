@@ -0,0 +1,414 @@
+use std::fmt;
+
+use ndarray::Array2;
+use serde::Serialize;
+
+use super::{
+    calibration::{ReliabilityBin, expected_calibration_error, reliability},
+    loss::Loss,
+};
+
+// Equal-width probability bins in the reliability diagram
+const RELIABILITY_BINS: usize = 10;
+
+// Counts of predictions at a decision threshold, treating "on" (target 1) as positive
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct ConfusionMatrix {
+    pub true_positives: usize,
+    pub false_positives: usize,
+    pub true_negatives: usize,
+    pub false_negatives: usize,
+}
+
+impl ConfusionMatrix {
+    // A probability is predicted positive when it is strictly above the threshold
+    pub fn new(probabilities: &[f64], targets: &[f64], threshold: f64) -> Self {
+        let mut m = Self::default();
+        for (&p, &y) in probabilities.iter().zip(targets) {
+            match (p > threshold, y > 0.5) {
+                (true, true) => m.true_positives += 1,
+                (true, false) => m.false_positives += 1,
+                (false, false) => m.true_negatives += 1,
+                (false, true) => m.false_negatives += 1,
+            }
+        }
+        m
+    }
+
+    pub fn positives(&self) -> usize {
+        self.true_positives + self.false_negatives
+    }
+
+    pub fn negatives(&self) -> usize {
+        self.true_negatives + self.false_positives
+    }
+
+    pub fn total(&self) -> usize {
+        self.positives() + self.negatives()
+    }
+
+    // Ratios with a zero denominator are reported as 0
+    pub fn accuracy(&self) -> f64 {
+        ratio(self.true_positives + self.true_negatives, self.total())
+    }
+
+    pub fn precision(&self) -> f64 {
+        ratio(
+            self.true_positives,
+            self.true_positives + self.false_positives,
+        )
+    }
+
+    // a.k.a. sensitivity or true positive rate
+    pub fn recall(&self) -> f64 {
+        ratio(self.true_positives, self.positives())
+    }
+
+    // a.k.a. true negative rate
+    pub fn specificity(&self) -> f64 {
+        ratio(self.true_negatives, self.negatives())
+    }
+
+    pub fn f1(&self) -> f64 {
+        ratio(
+            2 * self.true_positives,
+            2 * self.true_positives + self.false_positives + self.false_negatives,
+        )
+    }
+
+    // Mean of recall and specificity, so always predicting "off" scores 0.5 however rare "on" is
+    pub fn balanced_accuracy(&self) -> f64 {
+        (self.recall() + self.specificity()) / 2.0
+    }
+}
+
+fn ratio(n: usize, d: usize) -> f64 {
+    if d == 0 { 0.0 } else { n as f64 / d as f64 }
+}
+
+// Binary classification metrics for a single output against 0/1 targets
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Metrics {
+    pub samples: usize,
+    pub threshold: f64,
+    pub confusion: ConfusionMatrix,
+    pub accuracy: f64,
+    pub precision: f64,
+    pub recall: f64,
+    pub specificity: f64,
+    pub f1: f64,
+    pub balanced_accuracy: f64,
+    pub log_loss: f64,
+    pub brier_score: f64,
+    // Undefined (None) unless both classes are present
+    pub roc_auc: Option<f64>,
+    pub pr_auc: Option<f64>,
+    pub expected_calibration_error: f64,
+    pub reliability: Vec<ReliabilityBin>,
+}
+
+impl Metrics {
+    pub fn new(probabilities: &[f64], targets: &[f64], threshold: f64) -> Self {
+        let confusion = ConfusionMatrix::new(probabilities, targets, threshold);
+        let n = probabilities.len();
+        let reliability = reliability(probabilities, targets, RELIABILITY_BINS);
+        let a = Array2::from_shape_vec((1, n), probabilities.to_vec()).unwrap();
+        let y = Array2::from_shape_vec((1, n), targets.to_vec()).unwrap();
+        Self {
+            samples: n,
+            threshold,
+            confusion,
+            accuracy: confusion.accuracy(),
+            precision: confusion.precision(),
+            recall: confusion.recall(),
+            specificity: confusion.specificity(),
+            f1: confusion.f1(),
+            balanced_accuracy: confusion.balanced_accuracy(),
+            log_loss: if n == 0 {
+                0.0
+            } else {
+                Loss::BinaryCrossEntropy.loss(&a, &y)
+            },
+            brier_score: if n == 0 {
+                0.0
+            } else {
+                a.iter().zip(&y).map(|(a, y)| (a - y).powi(2)).sum::<f64>() / n as f64
+            },
+            roc_auc: roc_auc(probabilities, targets),
+            pr_auc: pr_auc(probabilities, targets),
+            expected_calibration_error: expected_calibration_error(&reliability),
+            reliability,
+        }
+    }
+}
+
+// What a threshold sweep maximises
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ThresholdCriterion {
+    F1,
+    // Youden's J: recall + specificity - 1
+    YoudensJ,
+}
+
+impl ThresholdCriterion {
+    pub fn score(&self, m: &ConfusionMatrix) -> f64 {
+        match self {
+            ThresholdCriterion::F1 => m.f1(),
+            ThresholdCriterion::YoudensJ => m.recall() + m.specificity() - 1.0,
+        }
+    }
+}
+
+// Tries every distinct probability as the threshold and returns the best one with its
+// score. Ties go to the highest threshold
+pub fn best_threshold(
+    probabilities: &[f64],
+    targets: &[f64],
+    criterion: ThresholdCriterion,
+) -> (f64, f64) {
+    let mut thresholds: Vec<f64> = probabilities.to_vec();
+    thresholds.sort_by(|a, b| b.total_cmp(a));
+    thresholds.dedup();
+    let groups = ranked_groups(probabilities, targets);
+    let positives: usize = groups.iter().map(|g| g.0).sum();
+    let negatives: usize = groups.iter().map(|g| g.1).sum();
+
+    // Walking down the thresholds, each group of tied samples becomes predicted positive
+    // as soon as the threshold drops below it
+    let mut m = ConfusionMatrix {
+        true_negatives: negatives,
+        false_negatives: positives,
+        ..Default::default()
+    };
+    let mut best = (0.5, f64::NEG_INFINITY);
+    for (k, &threshold) in thresholds.iter().enumerate() {
+        if k > 0 {
+            let (p, n) = groups[k - 1];
+            m.true_positives += p;
+            m.false_negatives -= p;
+            m.false_positives += n;
+            m.true_negatives -= n;
+        }
+        let score = criterion.score(&m);
+        if score > best.1 {
+            best = (threshold, score);
+        }
+    }
+    best
+}
+
+// Samples ordered by descending probability, grouped where probabilities tie,
+// as (positives, negatives) per group
+fn ranked_groups(probabilities: &[f64], targets: &[f64]) -> Vec<(usize, usize)> {
+    let mut order: Vec<usize> = (0..probabilities.len()).collect();
+    order.sort_by(|&i, &j| probabilities[j].total_cmp(&probabilities[i]));
+    let mut groups: Vec<(usize, usize)> = vec![];
+    let mut previous = None;
+    for i in order {
+        if previous != Some(probabilities[i]) {
+            groups.push((0, 0));
+            previous = Some(probabilities[i]);
+        }
+        let group = groups.last_mut().unwrap();
+        if targets[i] > 0.5 {
+            group.0 += 1;
+        } else {
+            group.1 += 1;
+        }
+    }
+    groups
+}
+
+// Area under the ROC curve: the probability that a random "on" sample scores higher than
+// a random "off" sample, counting ties as half
+pub fn roc_auc(probabilities: &[f64], targets: &[f64]) -> Option<f64> {
+    let groups = ranked_groups(probabilities, targets);
+    let positives: usize = groups.iter().map(|g| g.0).sum();
+    let negatives: usize = groups.iter().map(|g| g.1).sum();
+    if positives == 0 || negatives == 0 {
+        return None;
+    }
+    let mut area = 0.0;
+    let mut positives_above = 0;
+    for (p, n) in groups {
+        area += n as f64 * (positives_above as f64 + 0.5 * p as f64);
+        positives_above += p;
+    }
+    Some(area / (positives * negatives) as f64)
+}
+
+// Area under the precision-recall curve as average precision: the precision at each
+// distinct threshold weighted by the recall it adds
+pub fn pr_auc(probabilities: &[f64], targets: &[f64]) -> Option<f64> {
+    let groups = ranked_groups(probabilities, targets);
+    let positives: usize = groups.iter().map(|g| g.0).sum();
+    let negatives: usize = groups.iter().map(|g| g.1).sum();
+    if positives == 0 || negatives == 0 {
+        return None;
+    }
+    let (mut tp, mut fp, mut area) = (0, 0, 0.0);
+    for (p, n) in groups {
+        tp += p;
+        fp += n;
+        area += p as f64 / positives as f64 * ratio(tp, tp + fp);
+    }
+    Some(area)
+}
+
+fn optional(value: Option<f64>) -> String {
+    value.map_or("n/a".to_string(), |v| format!("{:.4}", v))
+}
+
+impl fmt::Display for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let c = &self.confusion;
+        writeln!(
+            f,
+            "{} samples ({} on, {} off); threshold = {}",
+            self.samples,
+            c.positives(),
+            c.negatives(),
+            self.threshold
+        )?;
+        writeln!(f, "{:>12}{:>15}{:>15}", "", "predicted on", "predicted off")?;
+        writeln!(
+            f,
+            "{:>12}{:>15}{:>15}",
+            "actual on", c.true_positives, c.false_negatives
+        )?;
+        writeln!(
+            f,
+            "{:>12}{:>15}{:>15}",
+            "actual off", c.false_positives, c.true_negatives
+        )?;
+        writeln!(f, "accuracy          = {:.4}", self.accuracy)?;
+        writeln!(f, "balanced accuracy = {:.4}", self.balanced_accuracy)?;
+        writeln!(f, "precision         = {:.4}", self.precision)?;
+        writeln!(f, "recall            = {:.4}", self.recall)?;
+        writeln!(f, "specificity       = {:.4}", self.specificity)?;
+        writeln!(f, "F1                = {:.4}", self.f1)?;
+        writeln!(f, "log loss          = {:.4}", self.log_loss)?;
+        writeln!(f, "Brier score       = {:.4}", self.brier_score)?;
+        writeln!(f, "ROC-AUC           = {}", optional(self.roc_auc))?;
+        writeln!(f, "PR-AUC            = {}", optional(self.pr_auc))?;
+        writeln!(
+            f,
+            "expected calibration error = {:.4}",
+            self.expected_calibration_error
+        )?;
+        write!(
+            f,
+            "{:>12}{:>8}{:>11}{:>10}",
+            "predicted", "count", "mean", "observed"
+        )?;
+        for bin in &self.reliability {
+            // the bar shows the observed rate, the | the mean prediction
+            let mut bar: Vec<char> = " ".repeat(20).chars().collect();
+            let observed = (bin.observed_rate * 20.0).round() as usize;
+            bar[..observed].fill('#');
+            bar[((bin.mean_predicted * 20.0) as usize).min(19)] = '|';
+            write!(
+                f,
+                "\n{:>5.1}-{:<6.1}{:>8}{:>11.4}{:>10.4} {}",
+                bin.lower,
+                bin.upper,
+                bin.count,
+                bin.mean_predicted,
+                bin.observed_rate,
+                bar.into_iter().collect::<String>()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ConfusionMatrix, Metrics, ThresholdCriterion, best_threshold, pr_auc, roc_auc};
+
+    #[test]
+    fn test_confusion_matrix_and_derived_ratios() {
+        let p = [0.9, 0.8, 0.3, 0.6, 0.1, 0.2, 0.4, 0.7];
+        let y = [1.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0];
+        let m = ConfusionMatrix::new(&p, &y, 0.5);
+        assert_eq!(
+            m,
+            ConfusionMatrix {
+                true_positives: 2,
+                false_positives: 2,
+                true_negatives: 3,
+                false_negatives: 1,
+            }
+        );
+        assert_eq!(m.accuracy(), 5.0 / 8.0);
+        assert_eq!(m.precision(), 0.5);
+        assert_eq!(m.recall(), 2.0 / 3.0);
+        assert_eq!(m.specificity(), 0.6);
+        assert!((m.f1() - 4.0 / 7.0).abs() < 1e-12);
+        assert!((m.balanced_accuracy() - (2.0 / 3.0 + 0.6) / 2.0).abs() < 1e-12);
+
+        // Raising the threshold trades recall for precision
+        let strict = ConfusionMatrix::new(&p, &y, 0.75);
+        assert_eq!(strict.precision(), 1.0);
+        assert_eq!(strict.recall(), 2.0 / 3.0);
+    }
+
+    #[test]
+    fn test_always_off_scores_well_on_accuracy_but_not_balanced_accuracy() {
+        let y: Vec<f64> = (0..100).map(|i| if i < 5 { 1.0 } else { 0.0 }).collect();
+        let m = Metrics::new(&[0.0; 100], &y, 0.5);
+        assert_eq!(m.accuracy, 0.95);
+        assert_eq!(m.balanced_accuracy, 0.5);
+        assert_eq!(m.f1, 0.0);
+        assert_eq!(m.roc_auc, Some(0.5));
+    }
+
+    #[test]
+    fn test_auc() {
+        let y = [1.0, 0.0, 1.0, 0.0];
+        // perfectly ranked, then perfectly reversed
+        assert_eq!(roc_auc(&[0.9, 0.1, 0.8, 0.2], &y), Some(1.0));
+        assert_eq!(pr_auc(&[0.9, 0.1, 0.8, 0.2], &y), Some(1.0));
+        assert_eq!(roc_auc(&[0.1, 0.9, 0.2, 0.8], &y), Some(0.0));
+        // positives ranked 1st and 3rd: precision 1 at recall 0.5, 2/3 at recall 1
+        let p = [0.9, 0.8, 0.7, 0.1];
+        assert_eq!(roc_auc(&p, &y), Some(0.75));
+        assert!((pr_auc(&p, &y).unwrap() - (0.5 + 1.0 / 3.0)).abs() < 1e-12);
+        // undefined with a single class
+        assert_eq!(roc_auc(&[0.2, 0.7], &[1.0, 1.0]), None);
+        assert_eq!(pr_auc(&[0.2, 0.7], &[0.0, 0.0]), None);
+    }
+
+    #[test]
+    fn test_log_loss_and_brier_score() {
+        let m = Metrics::new(&[0.8, 0.4], &[1.0, 0.0], 0.5);
+        assert!((m.log_loss - -(0.8f64.ln() + 0.6f64.ln()) / 2.0).abs() < 1e-12);
+        assert!((m.brier_score - (0.04 + 0.16) / 2.0).abs() < 1e-12);
+        let json = serde_json::to_value(&m).unwrap();
+        assert_eq!(json["confusion"]["true_positives"], 1);
+        assert_eq!(json["roc_auc"], 1.0);
+    }
+
+    #[test]
+    fn test_best_threshold_matches_a_brute_force_sweep() {
+        let p = [0.9, 0.8, 0.3, 0.6, 0.1, 0.2, 0.4, 0.7, 0.3, 0.35];
+        let y = [1.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 1.0];
+        for criterion in [ThresholdCriterion::F1, ThresholdCriterion::YoudensJ] {
+            let (threshold, score) = best_threshold(&p, &y, criterion);
+            let brute = p
+                .iter()
+                .map(|&t| criterion.score(&ConfusionMatrix::new(&p, &y, t)))
+                .fold(f64::NEG_INFINITY, f64::max);
+            assert_eq!(score, brute);
+            assert_eq!(
+                criterion.score(&ConfusionMatrix::new(&p, &y, threshold)),
+                score
+            );
+        }
+        // Two positives with no false alarms (above 0.7) ties with all five positives for
+        // three false alarms (above 0.2); the higher threshold wins
+        let (threshold, score) = best_threshold(&p, &y, ThresholdCriterion::YoudensJ);
+        assert_eq!(threshold, 0.7);
+        assert!((score - 0.4).abs() < 1e-12);
+    }
+}
@@ -1,11 +1,21 @@
-use ndarray::{Array, Array2};
+use ndarray::{Array2, Axis};
 use ndarray_rand::RandomExt;
+use ndarray_rand::rand::{Rng, SeedableRng, rngs::StdRng};
 use ndarray_rand::rand_distr::Uniform;
 use serde::{Deserialize, Serialize};
 
-use crate::mlp::fns;
+use crate::data::idg::FeatureSpec;
 
-use super::config::{MLPConfig, TrainingState};
+use super::{
+    calibration::Calibration,
+    config::{Evaluation, MLPConfig, TrainingState},
+    fns::OutputActivation,
+    history::HistorySummary,
+    loss::Loss,
+    observer::{Control, TrainingObserver},
+    optimizer::LayerState,
+    schedule::Plateau,
+};
 
 // Neural Network From Scratch: No Pytorch & Tensorflow; just pure math | 30 min theory + 30 min coding
 // https://youtu.be/A83BbHFoKb8?si=9zzxB-IFyPYYwV9c
@@ -13,58 +23,134 @@ use super::config::{MLPConfig, TrainingState};
 // Neural Networks From Scratch in Rust
 // https://www.youtube.com/watch?v=DKbz9pNXVdE&t=23s
 
+// Borrowed (inputs, targets) rows, one sample per row
+pub type Samples<'a> = (&'a [Vec<f64>], &'a [Vec<f64>]);
+
+// Weight and bias gradients, one matrix per layer
+type Gradients = (Vec<Array2<f64>>, Vec<Array2<f64>>);
+
+// Weights and biases without the optimizer state
+#[derive(Clone, Debug)]
+pub struct Parameters {
+    pub weights: Vec<Array2<f64>>,
+    pub biases: Vec<Array2<f64>>,
+}
+
 #[derive(Serialize, Deserialize)]
+#[allow(clippy::upper_case_acronyms)]
 pub struct MLP {
     config: MLPConfig,
     weights: Vec<Array2<f64>>,
     biases: Vec<Array2<f64>>,
+    // Per-layer optimizer state (velocities, moments), saved so training can resume
+    #[serde(default)]
+    optimizer_state: Vec<LayerState>,
+    // Number of weight updates applied so far
+    #[serde(default)]
+    steps: u64,
+    // Number of training epochs completed, across resumed runs
+    #[serde(default)]
+    epoch: u32,
+    // One summary per training run
+    #[serde(default)]
+    history: Vec<HistorySummary>,
+    // Reduce-on-plateau progress
+    #[serde(default)]
+    plateau: Plateau,
+    // Maps outputs to probabilities, fitted after training on held-out data
+    #[serde(default)]
+    calibration: Calibration,
+    // Decision threshold for the calibrated probability; 0.5 when None
+    #[serde(default)]
+    threshold: Option<f64>,
+    // Scheduled rate for the current epoch; config.learning_rate when None
+    #[serde(skip)]
+    learning_rate: Option<f64>,
     // Used to store hidden layer [A] values
     #[serde(skip)]
     a: Vec<Array2<f64>>,
+    // Dropout masks for the hidden layers of the last training forward pass, matching
+    // a[1..]: 0 for dropped units, 1 / keep_probability for kept ones. Empty otherwise
+    #[serde(skip)]
+    masks: Vec<Array2<f64>>,
 }
 
 impl MLP {
-    pub fn new(config: MLPConfig) -> Self {
+    pub fn new(mut config: MLPConfig) -> Self {
+        // Without a seed pick one at random, and keep it so the model can be reproduced
+        let seed = *config
+            .seed
+            .get_or_insert_with(ndarray_rand::rand::random::<u64>);
+        let mut rng = StdRng::seed_from_u64(seed);
+
         let mut weights = vec![];
         let mut biases = vec![];
 
         for i in 0..config.layers.len() - 1 {
-            weights.push(Array2::random(
-                (config.layers[i + 1], config.layers[i]),
-                Uniform::new(-0.5, 0.5),
-            ));
-            biases.push(Array2::random(
-                (config.layers[i + 1], 1),
-                Uniform::new(-0.5, 0.5),
-            ));
+            let (fan_in, fan_out) = (config.layers[i], config.layers[i + 1]);
+            weights.push(config.initializer.weights(fan_out, fan_in, &mut rng));
+            biases.push(if config.zero_biases {
+                Array2::zeros((fan_out, 1))
+            } else {
+                Array2::random_using((fan_out, 1), Uniform::new(-0.5, 0.5), &mut rng)
+            });
         }
 
+        let optimizer_state = weights
+            .iter()
+            .zip(biases.iter())
+            .map(|(w, b)| LayerState::new(w, b))
+            .collect();
+
         Self {
             config,
             weights,
             biases,
+            optimizer_state,
+            steps: 0,
+            epoch: 0,
+            history: vec![],
+            plateau: Plateau::default(),
+            calibration: Calibration::None,
+            threshold: None,
+            learning_rate: None,
             a: vec![],
+            masks: vec![],
         }
     }
 
-    fn outputfn(&self) -> fn(&Array2<f64>) -> Array2<f64> {
-        if let Some(l) = self.config.layers.last() {
-            // Networks with a single output neuron use sigmoid
-            if *l == 1 {
-                return fns::sigmoid;
-            }
-        }
-        fns::softmax
-    }
-
-    // TODO serialize activation function so it doesn't need to be passed in
-    pub fn load(
-        path: &str,
-        training_state_updated: Option<fn(TrainingState)>,
-    ) -> Result<Self, std::io::Error> {
+    pub fn load(path: &str) -> Result<Self, std::io::Error> {
         let file = std::fs::File::open(path)?;
-        let mut mlp: MLP = serde_json::from_reader(file)?;
-        mlp.config.training_state_updated = training_state_updated;
+        let mlp: MLP = serde_json::from_reader(file)?;
+        if mlp.config.layers.len() < 2 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("{} needs at least 2 layers", path),
+            ));
+        }
+        // A model is useless if its inputs can't be rebuilt
+        if let Some(features) = &mlp.config.features
+            && let Err(message) = features.validate()
+        {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("{}: {}", path, message),
+            ));
+        }
+        if let Some(features) = &mlp.config.features
+            && features.width() != mlp.config.layers[0]
+        {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "{} has {} inputs but its features ({}) need {}",
+                    path,
+                    mlp.config.layers[0],
+                    features,
+                    features.width()
+                ),
+            ));
+        }
         Ok(mlp)
     }
 
@@ -76,54 +162,216 @@ impl MLP {
     // z2 = w2 * a1 + b2
     // y = fout(z2)
 
-    pub fn feed_forward(&mut self, inputs: Vec<f64>) -> Array2<f64> {
+    // Scores every row (sample) of x, which has shape (samples, n_features), returning
+    // (samples, n_outputs). Nothing is cached, so a model can be shared between threads
+    pub fn predict(&self, x: &Array2<f64>) -> Array2<f64> {
         assert!(
-            self.config.layers[0] == inputs.len(),
+            self.config.layers[0] == x.ncols(),
             "Invalid number of inputs"
         );
 
+        let mut an = x.t().to_owned();
+        for i in 0..self.weights.len() {
+            let zn = &self.weights[i].dot(&an) + &self.biases[i];
+            an = self.activate(i, &zn);
+        }
+        an.reversed_axes()
+    }
+
+    // predict with the calibration applied to every output
+    pub fn probabilities(&self, x: &Array2<f64>) -> Array2<f64> {
+        self.predict(x).mapv(|p| self.calibration.apply(p))
+    }
+
+    // Applies layer i's activation: the output activation for the last layer
+    fn activate(&self, i: usize, z: &Array2<f64>) -> Array2<f64> {
+        if i == self.weights.len() - 1 {
+            self.config.output_activation.apply(z)
+        } else {
+            let f = self.config.activation(i);
+            z.mapv(|v| f.function(v))
+        }
+    }
+
+    // Training forward pass, caching each layer's activations for back_propagate.
+    // Inputs are stacked as columns, i.e. x has shape (n_features, batch)
+    fn forward(&mut self, x: Array2<f64>) -> Array2<f64> {
+        self.forward_with_dropout(x, None)
+    }
+
+    // Dropout is only applied when an rng is given, i.e. while training. Kept units are
+    // scaled by 1 / keep_probability so inference needs no rescaling
+    fn forward_with_dropout(
+        &mut self,
+        x: Array2<f64>,
+        mut rng: Option<&mut StdRng>,
+    ) -> Array2<f64> {
         // self.a[0] is X
-        let mut an = Array::from_shape_vec((self.config.layers[0], 1), inputs).unwrap();
+        let mut an = x;
         self.a = vec![an.clone()];
-        for i in 0..self.config.layers.len() - 1 {
+        self.masks.clear();
+        let output_layer = self.weights.len() - 1;
+        for i in 0..self.weights.len() {
+            // biases are (n, 1) and broadcast across the batch columns
             let zn = &self.weights[i].dot(&an) + &self.biases[i];
-            an = zn.mapv(|v| (self.config.activation.function)(&v));
-            if i == self.config.layers.len() - 1 {
-                an = self.outputfn()(&an);
+            an = self.activate(i, &zn);
+            if i != output_layer
+                && let Some(rng) = rng.as_deref_mut()
+            {
+                let p = self.config.keep_probability(i);
+                let mask = an.mapv(|_| if rng.gen_bool(p) { 1.0 / p } else { 0.0 });
+                an *= &mask;
+                self.masks.push(mask);
             }
             self.a.push(an.clone());
         }
         an
     }
 
-    pub fn train(&mut self, inputs: Vec<Vec<f64>>, targets: Vec<Vec<f64>>, epochs: u32) {
-        let mut mse: f64 = 0.0;
+    pub fn train(
+        &mut self,
+        inputs: Vec<Vec<f64>>,
+        targets: Vec<Vec<f64>>,
+        validation: Option<Samples>,
+        epochs: u32,
+    ) -> u32 {
+        self.train_with_observers(inputs, targets, validation, epochs, &mut [])
+    }
+
+    // Validation data is evaluated every epoch and passed to the observers, any of which
+    // can stop training. Training continues from the model's epoch counter, so schedules
+    // pick up where a resumed model left off. Returns the number of epochs actually run
+    pub fn train_with_observers(
+        &mut self,
+        inputs: Vec<Vec<f64>>,
+        targets: Vec<Vec<f64>>,
+        validation: Option<Samples>,
+        epochs: u32,
+        observers: &mut [&mut dyn TrainingObserver],
+    ) -> u32 {
+        let batch_size = self.config.batch_size.max(1);
+        let n_inputs = self.config.layers[0];
+        let n_outputs = self.config.layers[self.config.layers.len() - 1];
+        // Validation data and training accuracy are evaluated every reporting interval (1%
+        // of epochs), which is also when reduce-on-plateau looks at the loss, or every
+        // epoch if an observer records them all
+        let report_every = (epochs / 100).max(1);
+        let every_epoch = observers.iter().any(|o| o.needs_every_epoch());
+        // Dropout masks are seeded from the model seed and the steps taken so far, so
+        // resumed training doesn't repeat the masks of the first run
+        let dropout =
+            (0..self.config.layers.len() - 2).any(|l| self.config.keep_probability(l) < 1.0);
+        let mut rng = StdRng::seed_from_u64(self.config.seed.unwrap_or_default() ^ self.steps);
+
+        let mut epochs_run = 0;
         for i in 1..=epochs {
-            for j in 0..inputs.len() {
-                self.feed_forward(inputs[j].clone());
-                mse = self.back_propagate(
-                    //self.a[self.config.layers.len() - 1].clone(),
-                    Array::from_shape_vec(
-                        (self.config.layers[self.config.layers.len() - 1], 1),
-                        targets[j].clone(),
-                    )
-                    .unwrap(),
-                );
+            let learning_rate = self.config.schedule.rate(
+                self.config.learning_rate,
+                self.epoch,
+                self.config.warmup_epochs,
+                &self.plateau,
+            );
+            self.learning_rate = Some(learning_rate);
+            for observer in observers.iter_mut() {
+                observer.on_epoch_start(i, learning_rate);
             }
-            if epochs < 100 || i % (epochs / 100) == 0 {
-                if let Some(callback) = &self.config.training_state_updated {
-                    callback(TrainingState {
-                        total_epochs: epochs,
-                        epoch: i,
-                        mse,
-                    });
+
+            let mut total_loss = 0.0;
+            let batches = inputs.chunks(batch_size).zip(targets.chunks(batch_size));
+            for (batch, (xb, yb)) in batches.enumerate() {
+                let x = stack_columns(xb, n_inputs);
+                if dropout {
+                    self.forward_with_dropout(x, Some(&mut rng));
+                } else {
+                    self.forward(x);
                 }
+                let loss = self.back_propagate(stack_columns(yb, n_outputs));
+                total_loss += loss * xb.len() as f64;
+                for observer in observers.iter_mut() {
+                    observer.on_batch_end(i, batch, loss);
+                }
+            }
+            self.epoch += 1;
+            epochs_run = i;
+
+            let reporting_interval = i.is_multiple_of(report_every);
+            let measure = reporting_interval || every_epoch;
+            let state = TrainingState {
+                total_epochs: epochs,
+                epoch: i,
+                reporting_interval,
+                learning_rate,
+                loss: total_loss / inputs.len() as f64,
+                accuracy: measure.then(|| self.evaluate(&inputs, &targets).accuracy),
+                validation: validation
+                    .filter(|_| measure)
+                    .map(|(x, y)| self.evaluate(x, y)),
+            };
+            if reporting_interval {
+                let loss = state.validation.map_or(state.loss, |v| v.loss);
+                self.config.schedule.observe(loss, &mut self.plateau);
             }
+
+            // every observer sees the epoch, even once one has asked to stop
+            let mut control = Control::Continue;
+            for observer in observers.iter_mut() {
+                if observer.on_epoch_end(self, &state) == Control::Stop {
+                    control = Control::Stop;
+                }
+            }
+            if control == Control::Stop {
+                break;
+            }
+        }
+
+        for observer in observers.iter_mut() {
+            observer.on_training_end(self, epochs_run);
+        }
+        epochs_run
+    }
+
+    // A copy of the weights and biases, e.g. to restore the best seen during training
+    pub fn parameters(&self) -> Parameters {
+        Parameters {
+            weights: self.weights.clone(),
+            biases: self.biases.clone(),
+        }
+    }
+
+    pub fn set_parameters(&mut self, parameters: Parameters) {
+        self.weights = parameters.weights;
+        self.biases = parameters.biases;
+    }
+
+    // Mean loss and accuracy over the samples. With a single output a prediction
+    // is correct when it falls on the same side of 0.5 as the target, otherwise
+    // when the largest output matches the largest target
+    pub fn evaluate(&self, inputs: &[Vec<f64>], targets: &[Vec<f64>]) -> Evaluation {
+        let n_outputs = self.config.layers[self.config.layers.len() - 1];
+        let a = self
+            .predict(&stack_columns(inputs, self.config.layers[0]).reversed_axes())
+            .reversed_axes();
+        let y = stack_columns(targets, n_outputs);
+        let correct = a
+            .axis_iter(Axis(1))
+            .zip(y.axis_iter(Axis(1)))
+            .filter(|(a, y)| {
+                if n_outputs == 1 {
+                    (a[0] > 0.5) == (y[0] > 0.5)
+                } else {
+                    argmax(a.iter()) == argmax(y.iter())
+                }
+            })
+            .count();
+        Evaluation {
+            loss: self.config.loss.loss(&a, &y),
+            accuracy: correct as f64 / inputs.len() as f64,
         }
     }
 
     fn zl(&self, l: usize) -> Array2<f64> {
         // NB: a[l] works here instead of a[l-1] because a[0] = x
+        // With a batch, a[l] is (n, batch) and the (n, 1) bias broadcasts across columns
         self.weights[l].dot(&self.a[l]) + &self.biases[l]
     }
 
@@ -132,30 +380,156 @@ impl MLP {
     // http://neuralnetworksanddeeplearning.com/chap2.html
     // https://youtu.be/tIeHLnjs5U8?si=LYWn7ZYKv6FrOgcg
     // zl = wl.(al−1) + bl
+    // y has shape (n_outputs, batch); gradients are averaged over the batch columns.
+    // Returns the mean loss over the batch plus any weight penalty, measured before the
+    // weights are updated
     fn back_propagate(&mut self, y: Array2<f64>) -> f64 {
-        // sigma-prime: the derivitive of the activation function
-        let sigmap = self.config.activation.derivative;
+        let l = self.config.layers.len() - 1;
+        let loss = self.config.loss.loss(&self.a[l], &y) + self.penalty();
+        let (dws, dbs) = self.gradients(&y);
+        self.apply_gradients(&dws, &dbs);
+
+        loss
+    }
+
+    // Gradients of the loss plus penalty with respect to every weight and bias matrix,
+    // from the activations cached by the last forward pass
+    fn gradients(&self, y: &Array2<f64>) -> Gradients {
+        let batch = y.ncols() as f64;
         // start with the last layer - e.g. l=2 for 3 layers (1 hidden layer) [0,1,2]
         // NB: weight and bias layers are [0,1]
         let l = self.config.layers.len() - 1;
-        let mut deltal = (&self.a[l] - &y) * self.zl(l - 1).mapv(|v| sigmap(&v));
-        let mut dw = deltal.dot(&self.a[l - 1].t());
-        let mut db = &deltal;
-        // see here for other optimisation algorithms: https://towardsdatascience.com/neural-network-optimizers-from-scratch-in-python-af76ee087aab/
-        self.weights[l - 1] -= &dw.mapv(|v| v * self.config.learning_rate);
-        self.biases[l - 1] -= &db.mapv(|v| v * self.config.learning_rate);
+        let mut deltal = match (self.config.output_activation, self.config.loss) {
+            // For these pairings dC/dz simplifies to a - y, which avoids dividing by
+            // a vanishing a * (1 - a) when the output saturates
+            (OutputActivation::Sigmoid, Loss::BinaryCrossEntropy)
+            | (OutputActivation::Softmax, Loss::CategoricalCrossEntropy) => &self.a[l] - y,
+            // dC/dz = dC/da * f'(z)
+            (output, loss) => output.backward(&self.a[l], &loss.gradient(&self.a[l], y)),
+        };
+        let mut dws = vec![deltal.dot(&self.a[l - 1].t()) / batch];
+        let mut dbs = vec![deltal.sum_axis(Axis(1)).insert_axis(Axis(1)) / batch];
 
+        // Gradients for every layer are computed from the pre-update weights before any are applied
         for l in (0..self.config.layers.len() - 2).rev() {
-            deltal = self.weights[l + 1].t().dot(&deltal) * self.zl(l).mapv(|v| sigmap(&v));
+            // sigma-prime: the derivitive of this layer's activation function
+            let sigma = self.config.activation(l);
+            deltal =
+                self.weights[l + 1].t().dot(&deltal) * self.zl(l).mapv(|v| sigma.derivative(v));
+            // dropped units passed nothing forward, so they receive no gradient
+            if let Some(mask) = self.masks.get(l) {
+                deltal *= mask;
+            }
             // a[l] works here instead of a[l-1] because a[0] = x
-            dw = deltal.dot(&self.a[l].t());
-            db = &deltal;
-            self.weights[l] -= &dw.mapv(|v| v * self.config.learning_rate);
-            self.biases[l] -= &db.mapv(|v| v * self.config.learning_rate);
+            dws.push(deltal.dot(&self.a[l].t()) / batch);
+            dbs.push(deltal.sum_axis(Axis(1)).insert_axis(Axis(1)) / batch);
         }
-        // return mean-square error
-        deltal = deltal.mapv(|x| x * x);
-        deltal.sum() / deltal.len() as f64
+
+        // dws and dbs were pushed from the output layer backwards
+        dws.reverse();
+        dbs.reverse();
+        let (l1, l2) = (self.config.l1, self.config.l2);
+        if l1 != 0.0 || l2 != 0.0 {
+            for (dw, w) in dws.iter_mut().zip(self.weights.iter()) {
+                // d/dw of l1 * |w| + l2 / 2 * w^2, taking 0 as the L1 subgradient at w = 0
+                dw.zip_mut_with(w, |d, &w| {
+                    let sign = if w == 0.0 { 0.0 } else { w.signum() };
+                    *d += l1 * sign + l2 * w
+                });
+            }
+        }
+        (dws, dbs)
+    }
+
+    // L1 and L2 regularisation penalty over every weight matrix
+    fn penalty(&self) -> f64 {
+        let (l1, l2) = (self.config.l1, self.config.l2);
+        if l1 == 0.0 && l2 == 0.0 {
+            return 0.0;
+        }
+        self.weights
+            .iter()
+            .flat_map(|w| w.iter())
+            .map(|w| l1 * w.abs() + 0.5 * l2 * w * w)
+            .sum()
+    }
+
+    fn apply_gradients(&mut self, dws: &[Array2<f64>], dbs: &[Array2<f64>]) {
+        // Models saved before optimizer state was persisted start with fresh state
+        if self.optimizer_state.len() != self.weights.len() {
+            self.optimizer_state = self
+                .weights
+                .iter()
+                .zip(self.biases.iter())
+                .map(|(w, b)| LayerState::new(w, b))
+                .collect();
+        }
+        self.steps += 1;
+        let optimizer = self.config.optimizer;
+        let lr = self.learning_rate.unwrap_or(self.config.learning_rate);
+        for l in 0..self.weights.len() {
+            let state = &mut self.optimizer_state[l];
+            optimizer.update(
+                lr,
+                self.steps,
+                &mut self.weights[l],
+                &dws[l],
+                &mut state.weights,
+                true,
+            );
+            optimizer.update(
+                lr,
+                self.steps,
+                &mut self.biases[l],
+                &dbs[l],
+                &mut state.biases,
+                false,
+            );
+        }
+    }
+
+    pub fn epochs_trained(&self) -> u32 {
+        self.epoch
+    }
+
+    // Seeds weight initialisation, and shuffling when training resumes
+    pub fn seed(&self) -> Option<u64> {
+        self.config.seed
+    }
+
+    pub fn history(&self) -> &[HistorySummary] {
+        &self.history
+    }
+
+    pub fn add_history(&mut self, summary: HistorySummary) {
+        self.history.push(summary);
+    }
+
+    // The features the model was trained on. Models trained on raw inputs have none, and
+    // can't be fed inputs built from light samples
+    pub fn features(&self) -> Result<&FeatureSpec, std::io::Error> {
+        self.config.features.as_ref().ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "The model was trained on raw inputs, not features of light samples",
+            )
+        })
+    }
+
+    pub fn calibration(&self) -> &Calibration {
+        &self.calibration
+    }
+
+    pub fn set_calibration(&mut self, calibration: Calibration) {
+        self.calibration = calibration;
+    }
+
+    pub fn threshold(&self) -> f64 {
+        self.threshold.unwrap_or(0.5)
+    }
+
+    pub fn set_threshold(&mut self, threshold: f64) {
+        self.threshold = Some(threshold);
     }
 
     pub fn dump(&self, path: &str) -> Result<(), std::io::Error> {
@@ -165,12 +539,41 @@ impl MLP {
     }
 }
 
+fn argmax<'a>(values: impl Iterator<Item = &'a f64>) -> usize {
+    values
+        .enumerate()
+        .fold((0, f64::NEG_INFINITY), |(bi, bv), (i, v)| {
+            if *v > bv { (i, *v) } else { (bi, bv) }
+        })
+        .0
+}
+
+// Stacks row vectors (one per sample) into a (len, samples) matrix with one sample per column
+fn stack_columns(rows: &[Vec<f64>], len: usize) -> Array2<f64> {
+    Array2::from_shape_fn((len, rows.len()), |(r, c)| rows[c][r])
+}
+
 #[cfg(test)]
 mod test {
-    use crate::mlp::{self, config::MLPConfig, mlp::MLP};
+    use ndarray::{Array2, array};
+    use ndarray_rand::RandomExt;
+    use ndarray_rand::rand::{SeedableRng, rngs::StdRng};
+    use ndarray_rand::rand_distr::Uniform;
 
-    #[test]
-    fn test_mlp_serialize_to_file() {
+    use crate::data::idg::{Encoding, Feature, FeatureSpec};
+    use crate::mlp::{
+        calibration::Calibration,
+        config::{MLPConfig, TrainingState},
+        fns::{MLPFunc, OutputActivation},
+        init::Initializer,
+        loss::Loss,
+        mlp::MLP,
+        observer::{Checkpoint, Control, EarlyStopping, TrainingObserver},
+        optimizer::Optimizer,
+        schedule::Schedule,
+    };
+
+    fn xor_data() -> (Vec<Vec<f64>>, Vec<Vec<f64>>) {
         let inputs = vec![
             vec![0.0, 0.0],
             vec![0.0, 1.0],
@@ -178,54 +581,721 @@ mod test {
             vec![1.0, 1.0],
         ];
         let targets = vec![vec![0.0], vec![1.0], vec![1.0], vec![0.0]];
+        (inputs, targets)
+    }
+
+    fn clone_mlp(mlp: &MLP) -> MLP {
+        serde_json::from_str(&serde_json::to_string(mlp).unwrap()).unwrap()
+    }
 
+    #[test]
+    fn test_batched_forward_matches_single_samples() {
+        let (inputs, _) = xor_data();
         let mut mlp = MLP::new(MLPConfig {
             layers: vec![2, 3, 1],
-            activation: mlp::fns::LOGISTIC,
-            learning_rate: 0.5,
-            training_state_updated: None,
+            ..Default::default()
         });
+        let batched = mlp.forward(super::stack_columns(&inputs, 2));
+        for (j, input) in inputs.iter().enumerate() {
+            let single = mlp.forward(super::stack_columns(std::slice::from_ref(input), 2));
+            assert!((single[[0, 0]] - batched[[0, j]]).abs() < 1e-12);
+        }
+    }
 
-        mlp.train(inputs, targets, 10000);
-        mlp.dump("data/mlp.json").unwrap();
+    #[test]
+    fn test_predict_scores_rows_without_mutating_the_model() {
+        let (inputs, _) = xor_data();
+        let mut mlp = MLP::new(MLPConfig {
+            layers: vec![2, 4, 3],
+            output_activation: OutputActivation::Softmax,
+            ..Default::default()
+        });
+        let expected = mlp.forward(super::stack_columns(&inputs, 2));
 
-        let mut mlp = MLP::load("data/mlp.json", None).unwrap();
-        println!("{:?}", mlp.feed_forward(vec![0.0, 0.0]));
-        println!("{:?}", mlp.feed_forward(vec![0.0, 1.0]));
-        println!("{:?}", mlp.feed_forward(vec![1.0, 0.0]));
-        println!("{:?}", mlp.feed_forward(vec![1.0, 1.0]));
+        // Rows are samples, so the same matrix shared across threads gives the same scores
+        let mlp = std::sync::Arc::new(mlp);
+        let x = super::stack_columns(&inputs, 2).reversed_axes();
+        std::thread::scope(|scope| {
+            for _ in 0..2 {
+                let (mlp, x, expected) = (mlp.clone(), &x, &expected);
+                scope.spawn(move || {
+                    let predicted = mlp.predict(x);
+                    assert_eq!(predicted.dim(), (4, 3));
+                    assert!((&predicted - &expected.t()).iter().all(|d| d.abs() < 1e-12));
+                });
+            }
+        });
     }
 
     #[test]
-    fn test_mlp() {
-        // Train for XOR
-        let inputs = vec![
-            vec![0.0, 0.0],
-            vec![0.0, 1.0],
-            vec![1.0, 0.0],
-            vec![1.0, 1.0],
+    fn test_full_batch_update_is_mean_of_sample_updates() {
+        let (inputs, targets) = xor_data();
+        let mut batched = MLP::new(MLPConfig {
+            layers: vec![2, 3, 1],
+            batch_size: inputs.len(),
+            ..Default::default()
+        });
+        let start = clone_mlp(&batched);
+        batched.train(inputs.clone(), targets.clone(), None, 1);
+
+        // Apply each sample to a fresh copy of the starting weights and average the steps
+        let flat_step = |after: &MLP| -> Vec<f64> {
+            after
+                .weights
+                .iter()
+                .zip(start.weights.iter())
+                .flat_map(|(a, b)| (a - b).into_iter())
+                .collect()
+        };
+        let mut mean_step = vec![0.0; flat_step(&start).len()];
+        for (x, y) in inputs.iter().zip(targets.iter()) {
+            let mut single = clone_mlp(&start);
+            single.train(vec![x.clone()], vec![y.clone()], None, 1);
+            for (m, step) in mean_step.iter_mut().zip(flat_step(&single)) {
+                *m += step / inputs.len() as f64;
+            }
+        }
+
+        for (expected, actual) in mean_step.iter().zip(flat_step(&batched)) {
+            assert!((expected - actual).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_output_activation_is_applied_to_output_layer() {
+        let sigmoid = MLP::new(MLPConfig {
+            layers: vec![2, 3, 1],
+            activations: vec![MLPFunc::Tanh],
+            output_activation: OutputActivation::Sigmoid,
+            ..Default::default()
+        });
+        let mut identity = clone_mlp(&sigmoid);
+        identity.config.output_activation = OutputActivation::Identity;
+
+        let x = super::stack_columns(&xor_data().0, 2).reversed_axes();
+        let (z, a) = (identity.predict(&x), sigmoid.predict(&x));
+        for (z, a) in z.iter().zip(a.iter()) {
+            assert!((a - MLPFunc::Logistic.function(*z)).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_each_hidden_layer_uses_its_own_activation() {
+        let mut mlp = MLP::new(MLPConfig {
+            layers: vec![2, 8, 8, 1],
+            activations: vec![MLPFunc::ReLU, MLPFunc::Tanh],
+            ..Default::default()
+        });
+        let (inputs, _) = xor_data();
+        mlp.forward(super::stack_columns(&inputs, 2));
+        let relu = &mlp.a[1];
+        let z = mlp.zl(1);
+        assert!(relu.iter().all(|a| *a >= 0.0));
+        assert!(mlp.a[2].iter().zip(z.iter()).all(|(a, z)| *a == z.tanh()));
+    }
+
+    fn param(mlp: &mut MLP, bias: bool, l: usize) -> &mut Array2<f64> {
+        if bias {
+            &mut mlp.biases[l]
+        } else {
+            &mut mlp.weights[l]
+        }
+    }
+
+    // Compares the analytical gradient of every weight and bias with a central difference
+    // of the loss plus penalty
+    fn check_gradients(mut mlp: MLP, x: &Array2<f64>, y: &Array2<f64>) {
+        let h = 1e-6;
+        let objective = |mlp: &MLP| {
+            let a = mlp.predict(&x.t().to_owned()).reversed_axes();
+            mlp.config.loss.loss(&a, y) + mlp.penalty()
+        };
+        mlp.forward(x.clone());
+        let (dws, dbs) = mlp.gradients(y);
+        for (bias, grads) in [(false, &dws), (true, &dbs)] {
+            for (l, grad) in grads.iter().enumerate() {
+                for (idx, analytical) in grad.indexed_iter() {
+                    let original = param(&mut mlp, bias, l)[idx];
+                    param(&mut mlp, bias, l)[idx] = original + h;
+                    let plus = objective(&mlp);
+                    param(&mut mlp, bias, l)[idx] = original - h;
+                    let minus = objective(&mlp);
+                    param(&mut mlp, bias, l)[idx] = original;
+
+                    let numerical = (plus - minus) / (2.0 * h);
+                    assert!(
+                        (analytical - numerical).abs()
+                            <= 1e-7 + 1e-5 * (analytical.abs() + numerical.abs()),
+                        "{} {:?} of layer {} with {}, {} output and {:?}: {} vs {}",
+                        if bias { "bias" } else { "weight" },
+                        idx,
+                        l,
+                        mlp.config.activation(0),
+                        mlp.config.output_activation,
+                        mlp.config.loss,
+                        analytical,
+                        numerical
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_gradients_match_finite_differences() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let x = Array2::random_using((3, 5), Uniform::new(-1.0, 1.0), &mut rng);
+        // one-hot targets suit every loss
+        let y = Array2::from_shape_fn((2, 5), |(r, c)| ((r + c) % 2) as f64);
+
+        let activations = [
+            MLPFunc::Logistic,
+            MLPFunc::Tanh,
+            MLPFunc::ReLU,
+            MLPFunc::LeakyReLU(0.1),
+            MLPFunc::ELU(1.0),
+            MLPFunc::GELU,
+            MLPFunc::Softplus,
+            MLPFunc::Swish,
         ];
-        let targets = vec![vec![0.0], vec![1.0], vec![1.0], vec![0.0]];
+        let outputs = [
+            OutputActivation::Identity,
+            OutputActivation::Sigmoid,
+            OutputActivation::Softmax,
+            OutputActivation::Tanh,
+        ];
+        let losses = [
+            Loss::MSE,
+            Loss::BinaryCrossEntropy,
+            Loss::CategoricalCrossEntropy,
+            // small enough that both the quadratic and linear regions are exercised
+            Loss::Huber { delta: 0.3 },
+        ];
+        for activation in activations {
+            for output in outputs {
+                for loss in losses {
+                    // the cross-entropies need outputs in (0, 1)
+                    let cross_entropy = matches!(
+                        loss,
+                        Loss::BinaryCrossEntropy | Loss::CategoricalCrossEntropy
+                    );
+                    if cross_entropy
+                        && matches!(output, OutputActivation::Identity | OutputActivation::Tanh)
+                    {
+                        continue;
+                    }
+                    let mlp = MLP::new(MLPConfig {
+                        layers: vec![3, 4, 3, 2],
+                        activations: vec![activation],
+                        output_activation: output,
+                        loss,
+                        initializer: Initializer::XavierNormal,
+                        l1: 0.01,
+                        l2: 0.02,
+                        seed: Some(7),
+                        ..Default::default()
+                    });
+                    check_gradients(mlp, &x, &y);
+                }
+            }
+        }
+    }
 
+    #[test]
+    fn test_back_propagate_returns_batch_loss_before_update() {
+        let (inputs, targets) = xor_data();
         let mut mlp = MLP::new(MLPConfig {
             layers: vec![2, 3, 1],
-            activation: mlp::fns::LOGISTIC,
+            loss: Loss::BinaryCrossEntropy,
+            ..Default::default()
+        });
+        let x = super::stack_columns(&inputs, 2);
+        let y = super::stack_columns(&targets, 1);
+        let a = mlp.forward(x.clone());
+        let expected = Loss::BinaryCrossEntropy.loss(&a, &y);
+        assert!((mlp.back_propagate(y.clone()) - expected).abs() < 1e-12);
+
+        // Loss falls after a few hundred full-batch updates
+        for _ in 0..500 {
+            mlp.forward(x.clone());
+            mlp.back_propagate(y.clone());
+        }
+        let a = mlp.forward(x);
+        assert!(Loss::BinaryCrossEntropy.loss(&a, &y) < expected);
+    }
+
+    #[test]
+    fn test_regularization_adds_penalty_to_loss_and_gradient() {
+        let (inputs, targets) = xor_data();
+        let x = super::stack_columns(&inputs, 2);
+        let y = super::stack_columns(&targets, 1);
+        let mut plain = MLP::new(MLPConfig {
+            layers: vec![2, 3, 1],
+            seed: Some(5),
+            ..Default::default()
+        });
+        let mut regularized = clone_mlp(&plain);
+        regularized.config.l1 = 0.01;
+        regularized.config.l2 = 0.1;
+
+        let sum_abs: f64 = plain
+            .weights
+            .iter()
+            .flat_map(|w| w.iter())
+            .map(|w| w.abs())
+            .sum();
+        let sum_sq: f64 = plain
+            .weights
+            .iter()
+            .flat_map(|w| w.iter())
+            .map(|w| w * w)
+            .sum();
+        plain.forward(x.clone());
+        regularized.forward(x);
+        let plain_loss = plain.back_propagate(y.clone());
+        let loss = regularized.back_propagate(y);
+        assert!((loss - plain_loss - (0.01 * sum_abs + 0.05 * sum_sq)).abs() < 1e-12);
+
+        // With plain SGD each weight moves a further lr * (l1 * sign(w) + l2 * w)
+        let start = MLP::new(MLPConfig {
+            layers: vec![2, 3, 1],
+            seed: Some(5),
+            ..Default::default()
+        });
+        let lr = start.config.learning_rate;
+        for l in 0..start.weights.len() {
+            for ((w0, p), r) in start.weights[l]
+                .iter()
+                .zip(plain.weights[l].iter())
+                .zip(regularized.weights[l].iter())
+            {
+                let decay = lr * (0.01 * w0.signum() + 0.1 * w0);
+                assert!((p - r - decay).abs() < 1e-12);
+            }
+        }
+        assert_eq!(plain.biases, regularized.biases);
+        assert_eq!(clone_mlp(&regularized).config.l2, 0.1);
+    }
+
+    #[test]
+    fn test_dropout_masks_training_only() {
+        let (inputs, targets) = xor_data();
+        let mut mlp = MLP::new(MLPConfig {
+            layers: vec![2, 16, 1],
+            keep_probabilities: vec![0.5],
+            seed: Some(3),
+            ..Default::default()
+        });
+        let x = super::stack_columns(&inputs[1..2], 2);
+        let mut rng = ndarray_rand::rand::SeedableRng::seed_from_u64(1);
+        mlp.forward_with_dropout(x.clone(), Some(&mut rng));
+        let mask = mlp.masks[0].clone();
+        assert!(mask.iter().all(|m| *m == 0.0 || *m == 2.0));
+        assert!(mask.iter().any(|m| *m == 0.0));
+
+        // Incoming weights of dropped units are untouched by the update
+        let start = mlp.weights[0].clone();
+        mlp.back_propagate(super::stack_columns(&targets[1..2], 1));
+        for (unit, m) in mask.iter().enumerate() {
+            let changed = mlp.weights[0].row(unit) != start.row(unit);
+            assert_eq!(changed, *m != 0.0);
+        }
+
+        // Inference is deterministic and unscaled
+        mlp.train(inputs.clone(), targets, None, 10);
+        let x = super::stack_columns(&inputs, 2);
+        let a = mlp.predict(&x.t().to_owned());
+        assert_eq!(a, mlp.predict(&x.t().to_owned()));
+        assert_eq!(a.t(), mlp.forward(x));
+        assert!(mlp.masks.is_empty());
+    }
+
+    #[test]
+    fn test_scheduled_learning_rate_is_used_for_updates() {
+        let (inputs, targets) = xor_data();
+        // Halfway through a 2 epoch warmup the rate is 0.1 / 2
+        let mut warmup = MLP::new(MLPConfig {
+            layers: vec![2, 3, 1],
             learning_rate: 0.1,
-            training_state_updated: None,
+            warmup_epochs: 2,
+            schedule: Schedule::Exponential { gamma: 0.5 },
+            seed: Some(9),
+            ..Default::default()
+        });
+        let mut constant = MLP::new(MLPConfig {
+            layers: vec![2, 3, 1],
+            learning_rate: 0.05,
+            seed: Some(9),
+            ..Default::default()
+        });
+        warmup.train(inputs.clone(), targets.clone(), None, 1);
+        constant.train(inputs, targets, None, 1);
+        for (w, c) in warmup.weights.iter().zip(constant.weights.iter()) {
+            assert!((w - c).iter().all(|d| d.abs() < 1e-15));
+        }
+    }
+
+    #[test]
+    fn test_resumed_training_matches_uninterrupted_training() {
+        let (inputs, targets) = xor_data();
+        let mut uninterrupted = MLP::new(MLPConfig {
+            layers: vec![2, 3, 1],
+            optimizer: Optimizer::Adam {
+                beta1: 0.9,
+                beta2: 0.999,
+                epsilon: 1e-8,
+            },
+            schedule: Schedule::Step {
+                step_size: 2,
+                gamma: 0.5,
+            },
+            ..Default::default()
+        });
+        let mut resumed = clone_mlp(&uninterrupted);
+        uninterrupted.train(inputs.clone(), targets.clone(), None, 4);
+
+        resumed.train(inputs.clone(), targets.clone(), None, 2);
+        let mut resumed = clone_mlp(&resumed);
+        assert_eq!(resumed.epochs_trained(), 2);
+        resumed.train(inputs, targets, None, 2);
+        assert_eq!(resumed.epochs_trained(), 4);
+        assert_eq!(resumed.weights, uninterrupted.weights);
+        assert_eq!(resumed.biases, uninterrupted.biases);
+    }
+
+    #[test]
+    fn test_checkpoints_are_written_periodically() {
+        let (inputs, targets) = xor_data();
+        let path = std::env::temp_dir().join("hueml-checkpoint-test.json");
+        let path = path.to_str().unwrap().to_string();
+        let _ = std::fs::remove_file(&path);
+        let mut mlp = MLP::new(MLPConfig {
+            layers: vec![2, 3, 1],
+            ..Default::default()
+        });
+        let mut checkpoint = Checkpoint {
+            every: 3,
+            path: path.clone(),
+        };
+        mlp.train_with_observers(inputs, targets, None, 5, &mut [&mut checkpoint]);
+
+        // The last snapshot is from epoch 3
+        let checkpoint = MLP::load(&path).unwrap();
+        assert_eq!(checkpoint.epochs_trained(), 3);
+        assert_eq!(checkpoint.steps, 12);
+        assert_ne!(checkpoint.weights, mlp.weights);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_same_seed_trains_identical_models() {
+        let train = |seed| {
+            let (inputs, targets) = xor_data();
+            let mut mlp = MLP::new(MLPConfig {
+                layers: vec![2, 3, 1],
+                seed: Some(seed),
+                ..Default::default()
+            });
+            mlp.train(inputs, targets, None, 50);
+            mlp
+        };
+        let (a, b, c) = (train(7), train(7), train(8));
+        assert_eq!(a.weights, b.weights);
+        assert_eq!(a.biases, b.biases);
+        assert_ne!(a.weights, c.weights);
+    }
+
+    #[test]
+    fn test_seed_is_chosen_and_stored_when_not_given() {
+        let mlp = MLP::new(MLPConfig::default());
+        let seed = mlp.config.seed.expect("seed should be set by MLP::new");
+        let restored = clone_mlp(&mlp);
+        assert_eq!(restored.config.seed, Some(seed));
+        let rebuilt = MLP::new(MLPConfig {
+            seed: Some(seed),
+            ..Default::default()
+        });
+        assert_eq!(rebuilt.weights, mlp.weights);
+    }
+
+    #[test]
+    fn test_initializer_and_zero_biases() {
+        let mlp = MLP::new(MLPConfig {
+            layers: vec![3, 32, 32, 1],
+            initializer: Initializer::XavierUniform,
+            zero_biases: true,
+            ..Default::default()
+        });
+        assert!(mlp.biases.iter().all(|b| b.iter().all(|v| *v == 0.0)));
+        let limit = (6.0f64 / 64.0).sqrt();
+        assert!(mlp.weights[1].iter().all(|v| v.abs() <= limit));
+        let restored = clone_mlp(&mlp);
+        assert_eq!(restored.config.initializer, Initializer::XavierUniform);
+    }
+
+    #[test]
+    fn test_evaluate_reports_loss_and_accuracy() {
+        let (inputs, targets) = xor_data();
+        let mut mlp = MLP::new(MLPConfig {
+            layers: vec![2, 3, 1],
+            loss: Loss::BinaryCrossEntropy,
+            ..Default::default()
+        });
+        let a = mlp.forward(super::stack_columns(&inputs, 2));
+        let y = super::stack_columns(&targets, 1);
+        let expected_correct = a
+            .iter()
+            .zip(y.iter())
+            .filter(|(a, y)| (**a > 0.5) == (**y > 0.5))
+            .count();
+        let eval = mlp.evaluate(&inputs, &targets);
+        assert_eq!(eval.loss, Loss::BinaryCrossEntropy.loss(&a, &y));
+        assert_eq!(eval.accuracy, expected_correct as f64 / 4.0);
+    }
+
+    #[test]
+    fn test_early_stopping_restores_best_weights() {
+        let (inputs, targets) = xor_data();
+        // Validating against the opposite labels means validation loss only gets worse
+        // as XOR is learned, so the initial weights after the first interval stay best
+        let flipped: Vec<Vec<f64>> = targets.iter().map(|t| vec![1.0 - t[0]]).collect();
+        let mut mlp = MLP::new(MLPConfig {
+            layers: vec![2, 4, 1],
+            learning_rate: 0.5,
+            loss: Loss::BinaryCrossEntropy,
+            seed: Some(11),
+            ..Default::default()
+        });
+        let mut reference = clone_mlp(&mlp);
+        let mut early_stopping = EarlyStopping::new(3, 0.0);
+        let epochs_run = mlp.train_with_observers(
+            inputs.clone(),
+            targets.clone(),
+            Some((&inputs, &flipped)),
+            1000,
+            &mut [&mut early_stopping],
+        );
+        assert!(epochs_run < 1000);
+
+        // The restored weights are the best validation loss seen at any reporting interval
+        let restored_loss = mlp.evaluate(&inputs, &flipped).loss;
+        let mut best = f64::INFINITY;
+        for _ in 0..epochs_run / 10 {
+            reference.train(inputs.clone(), targets.clone(), None, 10);
+            best = best.min(reference.evaluate(&inputs, &flipped).loss);
+        }
+        assert_eq!(restored_loss, best);
+    }
+
+    // Captures what it is told and stops after stop_after epochs
+    #[derive(Default)]
+    struct Recorder {
+        stop_after: u32,
+        epochs_started: Vec<u32>,
+        batch_losses: Vec<f64>,
+        states: Vec<TrainingState>,
+        epochs_run: Option<u32>,
+    }
+
+    impl TrainingObserver for Recorder {
+        fn on_epoch_start(&mut self, epoch: u32, _learning_rate: f64) {
+            self.epochs_started.push(epoch);
+        }
+
+        fn on_batch_end(&mut self, _epoch: u32, _batch: usize, loss: f64) {
+            self.batch_losses.push(loss);
+        }
+
+        fn on_epoch_end(&mut self, _mlp: &MLP, state: &TrainingState) -> Control {
+            self.states.push(*state);
+            if state.epoch == self.stop_after {
+                Control::Stop
+            } else {
+                Control::Continue
+            }
+        }
+
+        fn on_training_end(&mut self, _mlp: &mut MLP, epochs_run: u32) {
+            self.epochs_run = Some(epochs_run);
+        }
+    }
+
+    #[test]
+    fn test_observers_see_every_epoch_and_batch_and_can_stop() {
+        let (inputs, targets) = xor_data();
+        let mut mlp = MLP::new(MLPConfig {
+            layers: vec![2, 3, 1],
+            batch_size: 2,
+            ..Default::default()
         });
+        let mut stopper = Recorder {
+            stop_after: 3,
+            ..Default::default()
+        };
+        let mut other = Recorder::default();
+        let epochs_run = mlp.train_with_observers(
+            inputs.clone(),
+            targets.clone(),
+            Some((&inputs, &targets)),
+            10,
+            &mut [&mut stopper, &mut other],
+        );
 
-        mlp.train(inputs, targets, 100000);
+        assert_eq!(epochs_run, 3);
+        assert_eq!(mlp.epochs_trained(), 3);
+        for recorder in [&stopper, &other] {
+            assert_eq!(recorder.epochs_started, vec![1, 2, 3]);
+            assert_eq!(recorder.batch_losses.len(), 6);
+            assert_eq!(recorder.states.len(), 3);
+            assert_eq!(recorder.epochs_run, Some(3));
+        }
+        // The epoch loss is the mean of its (equal sized) batch losses
+        let state = other.states[1];
+        assert!((state.loss - (other.batch_losses[2] + other.batch_losses[3]) / 2.0).abs() < 1e-12);
+        assert!(state.validation.is_some());
+    }
 
-        println!("{:?}", mlp.feed_forward(vec![0.0, 0.0]));
-        println!("{:?}", mlp.feed_forward(vec![0.0, 1.0]));
-        println!("{:?}", mlp.feed_forward(vec![1.0, 0.0]));
-        println!("{:?}", mlp.feed_forward(vec![1.0, 1.0]));
+    #[test]
+    fn test_optimizer_state_is_serialized() {
+        let (inputs, targets) = xor_data();
+        let mut mlp = MLP::new(MLPConfig {
+            layers: vec![2, 3, 1],
+            optimizer: Optimizer::Adam {
+                beta1: 0.9,
+                beta2: 0.999,
+                epsilon: 1e-8,
+            },
+            ..Default::default()
+        });
+        mlp.train(inputs, targets, None, 2);
+        assert_eq!(mlp.steps, 8);
+
+        let restored = clone_mlp(&mlp);
+        assert_eq!(restored.config.optimizer, mlp.config.optimizer);
+        assert_eq!(restored.steps, mlp.steps);
+        for (a, b) in restored
+            .optimizer_state
+            .iter()
+            .zip(mlp.optimizer_state.iter())
+        {
+            assert_eq!(a.weights.m, b.weights.m);
+            assert_eq!(a.weights.v, b.weights.v);
+            assert_eq!(a.biases.v, b.biases.v);
+        }
+    }
+
+    #[test]
+    fn test_calibration_and_threshold_are_serialized() {
+        let mut mlp = MLP::new(MLPConfig::default());
+        let x = array![[0.2, 0.9], [0.7, 0.1]];
+        mlp.set_calibration(Calibration::Platt { a: 2.0, b: -1.0 });
+        mlp.set_threshold(0.3);
+        let expected = mlp.predict(&x).mapv(|p| mlp.calibration().apply(p));
+        assert_eq!(mlp.probabilities(&x), expected);
+
+        let restored = clone_mlp(&mlp);
+        assert_eq!(restored.calibration(), mlp.calibration());
+        assert_eq!(restored.threshold(), 0.3);
+
+        // Models saved before calibration existed are uncalibrated with a 0.5 threshold
+        let mut json = serde_json::to_value(&mlp).unwrap();
+        json.as_object_mut().unwrap().remove("calibration");
+        json.as_object_mut().unwrap().remove("threshold");
+        let old: MLP = serde_json::from_value(json).unwrap();
+        assert_eq!(old.calibration(), &Calibration::None);
+        assert_eq!(old.threshold(), 0.5);
+        assert_eq!(old.probabilities(&x), old.predict(&x));
+    }
+
+    #[test]
+    fn test_features_are_stored_and_checked_on_load() {
+        let path = std::env::temp_dir().join("hueml-features-test.json");
+        let path = path.to_str().unwrap();
+        let mut mlp = MLP::new(MLPConfig {
+            layers: vec![3, 4, 1],
+            features: Some(FeatureSpec::default()),
+            ..Default::default()
+        });
+        mlp.dump(path).unwrap();
+        assert_eq!(
+            MLP::load(path).unwrap().features().unwrap(),
+            &FeatureSpec::default()
+        );
+
+        // Models saved before features were stored get the original three
+        let mut json = serde_json::to_value(&mlp).unwrap();
+        json["config"].as_object_mut().unwrap().remove("features");
+        let old: MLP = serde_json::from_value(json).unwrap();
+        assert_eq!(old.config.features, Some(FeatureSpec::default()));
+
+        // Features that don't fit the input layer are an error, not a panic at predict time
+        mlp.config.features = Some(FeatureSpec {
+            features: vec![Feature::TimeOfDay {
+                encoding: Encoding::Cyclic,
+            }],
+            ..FeatureSpec::default()
+        });
+        mlp.dump(path).unwrap();
+        let error = MLP::load(path).err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+
+        // Models trained on raw inputs have no features to build inputs from
+        mlp.config.features = None;
+        mlp.dump(path).unwrap();
+        assert!(MLP::load(path).unwrap().features().is_err());
+
+        // Nor is a model without an input and output layer
+        let mut json = serde_json::to_value(&mlp).unwrap();
+        json["config"]["layers"] = serde_json::json!([3]);
+        std::fs::write(path, json.to_string()).unwrap();
+        let error = MLP::load(path).err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    // XOR is 1 for the middle two inputs
+    fn assert_learned_xor(outputs: &Array2<f64>) {
+        let predicted: Vec<bool> = outputs.column(0).iter().map(|&p| p > 0.5).collect();
+        assert_eq!(predicted, vec![false, true, true, false], "{:?}", outputs);
+    }
+
+    #[test]
+    fn test_mlp_serialize_to_file() {
+        let (inputs, targets) = xor_data();
+        let mut mlp = MLP::new(MLPConfig {
+            layers: vec![2, 3, 1],
+            activations: vec![MLPFunc::Logistic],
+            learning_rate: 0.5,
+            batch_size: 1,
+            seed: Some(1),
+            ..Default::default()
+        });
+        mlp.train(inputs, targets, None, 10000);
+
+        let path = std::env::temp_dir().join("hueml-serialize-test.json");
+        let path = path.to_str().unwrap();
+        mlp.dump(path).unwrap();
+        let loaded = MLP::load(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        let x = array![[0.0, 0.0], [0.0, 1.0], [1.0, 0.0], [1.0, 1.0]];
+        assert_learned_xor(&loaded.predict(&x));
+        assert_eq!(loaded.predict(&x), mlp.predict(&x));
+    }
+
+    #[test]
+    fn test_mlp() {
+        let (inputs, targets) = xor_data();
+        let mut mlp = MLP::new(MLPConfig {
+            layers: vec![2, 3, 1],
+            activations: vec![MLPFunc::Logistic],
+            learning_rate: 0.1,
+            batch_size: 1,
+            seed: Some(1),
+            ..Default::default()
+        });
+        mlp.train(inputs, targets, None, 100000);
 
-        /*
-        [[0.010837591978139343]], shape=[1, 1], strides=[1, 1], layout=CFcf (0xf), const ndim=2
-        [[0.9896457406477849]], shape=[1, 1], strides=[1, 1], layout=CFcf (0xf), const ndim=2
-        [[0.989648726249577]], shape=[1, 1], strides=[1, 1], layout=CFcf (0xf), const ndim=2
-        [[0.010077380921864295]], shape=[1, 1], strides=[1, 1], layout=CFcf (0xf), const ndim=2
-                                 */
+        assert_learned_xor(&mlp.predict(&array![[0.0, 0.0], [0.0, 1.0], [1.0, 0.0], [1.0, 1.0]]));
     }
 }
@@ -1,5 +1,14 @@
+pub mod calibration;
 pub mod config;
 pub mod fns;
+pub mod history;
+pub mod init;
+pub mod loss;
+pub mod metrics;
+#[allow(clippy::module_inception)]
 pub mod mlp;
+pub mod observer;
+pub mod optimizer;
+pub mod schedule;
 
 mod tests;
@@ -0,0 +1,175 @@
+use super::{
+    config::TrainingState,
+    mlp::{MLP, Parameters},
+};
+
+// Whether training should carry on after an observer hook
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Control {
+    Continue,
+    Stop,
+}
+
+// Hooks called by MLP::train_with_observers. Epochs are numbered from 1 within the run.
+// Any observer returning Control::Stop from on_epoch_end ends training after that epoch,
+// but every observer still sees the epoch and on_training_end
+pub trait TrainingObserver {
+    // Whether on_epoch_end should get validation metrics and training accuracy at every
+    // epoch, not just every reporting interval
+    fn needs_every_epoch(&self) -> bool {
+        false
+    }
+
+    fn on_epoch_start(&mut self, _epoch: u32, _learning_rate: f64) {}
+
+    // Mean loss over the batch, before the update computed from it is applied
+    fn on_batch_end(&mut self, _epoch: u32, _batch: usize, _loss: f64) {}
+
+    fn on_epoch_end(&mut self, _mlp: &MLP, _state: &TrainingState) -> Control {
+        Control::Continue
+    }
+
+    fn on_training_end(&mut self, _mlp: &mut MLP, _epochs_run: u32) {}
+}
+
+// Prints progress every `every` epochs and always at the last epoch
+pub struct Console {
+    pub every: u32,
+}
+
+impl TrainingObserver for Console {
+    fn on_epoch_end(&mut self, _mlp: &MLP, ts: &TrainingState) -> Control {
+        if !ts.epoch.is_multiple_of(self.every.max(1)) && ts.epoch != ts.total_epochs {
+            return Control::Continue;
+        }
+        match ts.validation {
+            Some(v) => println!(
+                "Epoch {} of {}; learning rate = {:.6}; loss = {}; validation loss = {}, accuracy = {:.1}%",
+                ts.epoch,
+                ts.total_epochs,
+                ts.learning_rate,
+                ts.loss,
+                v.loss,
+                v.accuracy * 100.0
+            ),
+            None => println!(
+                "Epoch {} of {}; learning rate = {:.6}; loss = {}",
+                ts.epoch, ts.total_epochs, ts.learning_rate, ts.loss
+            ),
+        }
+        Control::Continue
+    }
+}
+
+// Stops training once validation loss has failed to improve by more than min_delta
+// for patience consecutive reporting intervals, then restores the best weights seen.
+// Epochs between reporting intervals, or without validation data, are ignored
+pub struct EarlyStopping {
+    pub patience: u32,
+    pub min_delta: f64,
+    best: Option<(f64, Parameters)>,
+    intervals_without_improvement: u32,
+}
+
+impl EarlyStopping {
+    pub fn new(patience: u32, min_delta: f64) -> Self {
+        Self {
+            patience,
+            min_delta,
+            best: None,
+            intervals_without_improvement: 0,
+        }
+    }
+}
+
+impl TrainingObserver for EarlyStopping {
+    fn on_epoch_end(&mut self, mlp: &MLP, ts: &TrainingState) -> Control {
+        let (true, Some(v)) = (ts.reporting_interval, ts.validation) else {
+            return Control::Continue;
+        };
+        if self
+            .best
+            .as_ref()
+            .is_none_or(|(loss, _)| v.loss < loss - self.min_delta)
+        {
+            self.best = Some((v.loss, mlp.parameters()));
+            self.intervals_without_improvement = 0;
+            return Control::Continue;
+        }
+        self.intervals_without_improvement += 1;
+        if self.intervals_without_improvement >= self.patience {
+            Control::Stop
+        } else {
+            Control::Continue
+        }
+    }
+
+    fn on_training_end(&mut self, mlp: &mut MLP, _epochs_run: u32) {
+        if let Some((_, parameters)) = self.best.take() {
+            mlp.set_parameters(parameters);
+        }
+    }
+}
+
+// Writes the model, including optimizer state and the epoch counter, to path every
+// so many epochs (counted across resumed runs)
+pub struct Checkpoint {
+    pub every: u32,
+    pub path: String,
+}
+
+impl TrainingObserver for Checkpoint {
+    fn on_epoch_end(&mut self, mlp: &MLP, _ts: &TrainingState) -> Control {
+        // A failed snapshot shouldn't throw away the training run
+        if mlp.epochs_trained().is_multiple_of(self.every.max(1))
+            && let Err(e) = mlp.dump(&self.path)
+        {
+            eprintln!("Failed to write checkpoint {}: {}", self.path, e);
+        }
+        Control::Continue
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Control, EarlyStopping, TrainingObserver};
+    use crate::mlp::{
+        config::{Evaluation, MLPConfig, TrainingState},
+        mlp::MLP,
+    };
+
+    #[test]
+    fn test_early_stopping_stops_after_patience_intervals() {
+        let mlp = MLP::new(MLPConfig::default());
+        let mut early_stopping = EarlyStopping::new(3, 0.0);
+        let controls: Vec<Control> = [1.0, 2.0, 2.0, 2.0]
+            .iter()
+            .enumerate()
+            .map(|(i, &loss)| {
+                let state = TrainingState {
+                    total_epochs: 100,
+                    epoch: i as u32 + 1,
+                    reporting_interval: true,
+                    learning_rate: 0.1,
+                    loss,
+                    accuracy: Some(0.5),
+                    validation: Some(Evaluation {
+                        loss,
+                        accuracy: 0.5,
+                    }),
+                };
+                early_stopping.on_epoch_end(&mlp, &state)
+            })
+            .collect();
+        // the best interval, then 3 without improvement
+        assert_eq!(
+            controls,
+            vec![
+                Control::Continue,
+                Control::Continue,
+                Control::Continue,
+                Control::Stop
+            ]
+        );
+    }
+}
@@ -0,0 +1,198 @@
+use ndarray::Array2;
+use serde::{Deserialize, Serialize};
+
+// Neural Network Optimizers from Scratch in Python
+// https://towardsdatascience.com/neural-network-optimizers-from-scratch-in-python-af76ee087aab/
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+#[allow(clippy::upper_case_acronyms)]
+pub enum Optimizer {
+    // w -= lr * dw
+    #[default]
+    SGD,
+    // SGD with Nesterov momentum (PyTorch formulation)
+    Nesterov {
+        momentum: f64,
+    },
+    RMSProp {
+        rho: f64,
+        epsilon: f64,
+    },
+    Adam {
+        beta1: f64,
+        beta2: f64,
+        epsilon: f64,
+    },
+    // Adam with decoupled weight decay, applied to weights but not biases
+    AdamW {
+        beta1: f64,
+        beta2: f64,
+        epsilon: f64,
+        weight_decay: f64,
+    },
+}
+
+// Running moments for a single weight or bias matrix.
+// m holds the velocity (Nesterov) or first moment (Adam), v the second moment (RMSProp, Adam)
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ParamState {
+    pub m: Array2<f64>,
+    pub v: Array2<f64>,
+}
+
+impl ParamState {
+    pub fn zeros(shape: (usize, usize)) -> Self {
+        Self {
+            m: Array2::zeros(shape),
+            v: Array2::zeros(shape),
+        }
+    }
+}
+
+// Optimizer state for one layer, kept alongside the layer's weights and biases
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LayerState {
+    pub weights: ParamState,
+    pub biases: ParamState,
+}
+
+impl LayerState {
+    pub fn new(weights: &Array2<f64>, biases: &Array2<f64>) -> Self {
+        Self {
+            weights: ParamState::zeros(weights.dim()),
+            biases: ParamState::zeros(biases.dim()),
+        }
+    }
+}
+
+impl Optimizer {
+    // Applies one update to param in place.
+    // t is the 1-based update counter used for Adam's bias correction
+    // and decay selects whether decoupled weight decay applies (weights only)
+    pub fn update(
+        &self,
+        learning_rate: f64,
+        t: u64,
+        param: &mut Array2<f64>,
+        grad: &Array2<f64>,
+        state: &mut ParamState,
+        decay: bool,
+    ) {
+        match *self {
+            Optimizer::SGD => {
+                param.scaled_add(-learning_rate, grad);
+            }
+            Optimizer::Nesterov { momentum } => {
+                // v = mu * v + g; w -= lr * (g + mu * v)
+                state.m = &state.m * momentum + grad;
+                param.scaled_add(-learning_rate, &(grad + &(&state.m * momentum)));
+            }
+            Optimizer::RMSProp { rho, epsilon } => {
+                state.v = &state.v * rho + &grad.mapv(|g| (1.0 - rho) * g * g);
+                let step = grad / &state.v.mapv(|v| v.sqrt() + epsilon);
+                param.scaled_add(-learning_rate, &step);
+            }
+            Optimizer::Adam {
+                beta1,
+                beta2,
+                epsilon,
+            } => adam(
+                learning_rate,
+                t,
+                param,
+                grad,
+                state,
+                (beta1, beta2, epsilon),
+            ),
+            Optimizer::AdamW {
+                beta1,
+                beta2,
+                epsilon,
+                weight_decay,
+            } => {
+                if decay {
+                    *param *= 1.0 - learning_rate * weight_decay;
+                }
+                adam(
+                    learning_rate,
+                    t,
+                    param,
+                    grad,
+                    state,
+                    (beta1, beta2, epsilon),
+                );
+            }
+        }
+    }
+}
+
+fn adam(
+    learning_rate: f64,
+    t: u64,
+    param: &mut Array2<f64>,
+    grad: &Array2<f64>,
+    state: &mut ParamState,
+    (beta1, beta2, epsilon): (f64, f64, f64),
+) {
+    state.m = &state.m * beta1 + &(grad * (1.0 - beta1));
+    state.v = &state.v * beta2 + &grad.mapv(|g| (1.0 - beta2) * g * g);
+    // bias-corrected moment estimates
+    let m_hat = &state.m / (1.0 - beta1.powi(t as i32));
+    let v_hat = &state.v / (1.0 - beta2.powi(t as i32));
+    let step = m_hat / v_hat.mapv(|v| v.sqrt() + epsilon);
+    param.scaled_add(-learning_rate, &step);
+}
+
+#[cfg(test)]
+mod test {
+    use ndarray::array;
+
+    use super::{Optimizer, ParamState};
+
+    #[test]
+    fn test_adam_first_step_moves_by_learning_rate() {
+        // With bias correction the first Adam step is lr * sign(g), whatever the gradient scale
+        let opt = Optimizer::Adam {
+            beta1: 0.9,
+            beta2: 0.999,
+            epsilon: 1e-8,
+        };
+        let mut w = array![[1.0, 1.0]];
+        let mut state = ParamState::zeros(w.dim());
+        opt.update(0.1, 1, &mut w, &array![[250.0, -0.003]], &mut state, true);
+        assert!((w[[0, 0]] - 0.9).abs() < 1e-6);
+        assert!((w[[0, 1]] - 1.1).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_nesterov_accumulates_velocity() {
+        let opt = Optimizer::Nesterov { momentum: 0.5 };
+        let mut w = array![[0.0]];
+        let mut state = ParamState::zeros(w.dim());
+        let g = array![[1.0]];
+        // v = 1, w -= 1 * (1 + 0.5 * 1)
+        opt.update(1.0, 1, &mut w, &g, &mut state, true);
+        assert_eq!(w[[0, 0]], -1.5);
+        // v = 1.5, w -= 1 * (1 + 0.5 * 1.5)
+        opt.update(1.0, 2, &mut w, &g, &mut state, true);
+        assert_eq!(w[[0, 0]], -3.25);
+    }
+
+    #[test]
+    fn test_adamw_decays_weights_but_not_when_disabled() {
+        let opt = Optimizer::AdamW {
+            beta1: 0.9,
+            beta2: 0.999,
+            epsilon: 1e-8,
+            weight_decay: 0.5,
+        };
+        let zero = array![[0.0]];
+        let mut w = array![[2.0]];
+        let mut b = array![[2.0]];
+        opt.update(0.1, 1, &mut w, &zero, &mut ParamState::zeros((1, 1)), true);
+        opt.update(0.1, 1, &mut b, &zero, &mut ParamState::zeros((1, 1)), false);
+        assert!((w[[0, 0]] - 1.9).abs() < 1e-12);
+        assert_eq!(b[[0, 0]], 2.0);
+    }
+}
@@ -0,0 +1,178 @@
+use std::f64::consts::PI;
+
+use serde::{Deserialize, Serialize};
+
+// SGDR: Stochastic Gradient Descent with Warm Restarts (Loshchilov & Hutter)
+// https://arxiv.org/abs/1608.03983
+
+// How the learning rate changes from epoch to epoch. Rates are computed from the
+// configured learning_rate and the 0-based epoch after any warmup
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Schedule {
+    #[default]
+    Constant,
+    // lr * gamma^(epoch / step_size)
+    Step {
+        step_size: u32,
+        gamma: f64,
+    },
+    // lr * gamma^epoch
+    Exponential {
+        gamma: f64,
+    },
+    // Cosine annealing from lr down to min_lr over period epochs, then restarting
+    // with the period multiplied by multiplier
+    CosineWarmRestarts {
+        period: u32,
+        multiplier: u32,
+        min_lr: f64,
+    },
+    // Multiplies the rate by factor when validation loss (or training loss without
+    // validation data) fails to improve for patience reporting intervals
+    ReduceOnPlateau {
+        factor: f64,
+        patience: u32,
+        min_lr: f64,
+    },
+}
+
+// Progress of a reduce-on-plateau schedule, saved with the model
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Plateau {
+    pub best: Option<f64>,
+    pub intervals_without_improvement: u32,
+    // Multiplier applied to the configured learning rate
+    pub scale: f64,
+}
+
+impl Default for Plateau {
+    fn default() -> Self {
+        Self {
+            best: None,
+            intervals_without_improvement: 0,
+            scale: 1.0,
+        }
+    }
+}
+
+impl Schedule {
+    // Learning rate for the given 0-based epoch. Linear warmup ramps up to lr over the
+    // first warmup_epochs, after which the schedule starts from its own epoch 0
+    pub fn rate(&self, lr: f64, epoch: u32, warmup_epochs: u32, plateau: &Plateau) -> f64 {
+        if epoch < warmup_epochs {
+            return lr * (epoch + 1) as f64 / warmup_epochs as f64;
+        }
+        let epoch = epoch - warmup_epochs;
+        match *self {
+            Schedule::Constant => lr,
+            Schedule::Step { step_size, gamma } => {
+                lr * gamma.powi((epoch / step_size.max(1)) as i32)
+            }
+            Schedule::Exponential { gamma } => lr * gamma.powi(epoch as i32),
+            Schedule::CosineWarmRestarts {
+                period,
+                multiplier,
+                min_lr,
+            } => {
+                // Find the position within the current cycle
+                let (mut t, mut cycle) = (epoch, period.max(1));
+                while t >= cycle {
+                    t -= cycle;
+                    cycle = cycle.saturating_mul(multiplier.max(1));
+                }
+                min_lr + (lr - min_lr) * (1.0 + (PI * t as f64 / cycle as f64).cos()) / 2.0
+            }
+            Schedule::ReduceOnPlateau { min_lr, .. } => (lr * plateau.scale).max(min_lr),
+        }
+    }
+
+    // Records the loss at a reporting interval; only reduce-on-plateau uses it
+    pub fn observe(&self, loss: f64, plateau: &mut Plateau) {
+        let Schedule::ReduceOnPlateau {
+            factor, patience, ..
+        } = *self
+        else {
+            return;
+        };
+        if plateau.best.is_none_or(|best| loss < best) {
+            plateau.best = Some(loss);
+            plateau.intervals_without_improvement = 0;
+        } else {
+            plateau.intervals_without_improvement += 1;
+            if plateau.intervals_without_improvement > patience {
+                plateau.scale *= factor;
+                plateau.intervals_without_improvement = 0;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Plateau, Schedule};
+
+    fn rates(schedule: Schedule, warmup: u32, epochs: u32) -> Vec<f64> {
+        (0..epochs)
+            .map(|e| schedule.rate(1.0, e, warmup, &Plateau::default()))
+            .collect()
+    }
+
+    #[test]
+    fn test_step_and_exponential_decay() {
+        let step = Schedule::Step {
+            step_size: 2,
+            gamma: 0.5,
+        };
+        assert_eq!(rates(step, 0, 5), vec![1.0, 1.0, 0.5, 0.5, 0.25]);
+        let exponential = Schedule::Exponential { gamma: 0.9 };
+        assert!((rates(exponential, 0, 3)[2] - 0.81).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_warmup_ramps_up_before_the_schedule() {
+        let step = Schedule::Step {
+            step_size: 1,
+            gamma: 0.5,
+        };
+        assert_eq!(rates(step, 4, 6), vec![0.25, 0.5, 0.75, 1.0, 1.0, 0.5]);
+    }
+
+    #[test]
+    fn test_cosine_restarts_with_growing_periods() {
+        let cosine = Schedule::CosineWarmRestarts {
+            period: 4,
+            multiplier: 2,
+            min_lr: 0.0,
+        };
+        let r = rates(cosine, 0, 13);
+        assert_eq!(r[0], 1.0);
+        assert!((r[2] - 0.5).abs() < 1e-12);
+        // restarts at epoch 4 with a period of 8, then at epoch 12
+        assert_eq!(r[4], 1.0);
+        assert!((r[8] - 0.5).abs() < 1e-12);
+        assert_eq!(r[12], 1.0);
+        assert!(r[5..12].windows(2).all(|w| w[1] < w[0]));
+    }
+
+    #[test]
+    fn test_reduce_on_plateau() {
+        let schedule = Schedule::ReduceOnPlateau {
+            factor: 0.1,
+            patience: 1,
+            min_lr: 0.05,
+        };
+        let mut plateau = Plateau::default();
+        for loss in [1.0, 0.9, 0.95, 0.92] {
+            schedule.observe(loss, &mut plateau);
+        }
+        assert!((schedule.rate(1.0, 10, 0, &plateau) - 0.1).abs() < 1e-12);
+        // improvement resets the count
+        schedule.observe(0.5, &mut plateau);
+        assert_eq!(plateau.intervals_without_improvement, 0);
+        for _ in 0..2 {
+            schedule.observe(0.6, &mut plateau);
+        }
+        assert_eq!(schedule.rate(1.0, 10, 0, &plateau), 0.05);
+    }
+}
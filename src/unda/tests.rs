@@ -16,7 +16,7 @@ use unda::core::{
 
 #[cfg(test)]
 use crate::{
-    data::{idg::make_input_data_vector, types::LightSample},
+    data::{idg::FeatureSpec, types::LightSample},
     db::LightState,
 };
 
@@ -61,7 +61,7 @@ fn hello() {
         .collect();
 
     for le in res.iter() {
-        let input_vec = make_input_data_vector(le);
+        let input_vec = FeatureSpec::default().make(le);
         inputs.push(vec![
             input_vec[0] as f32,
             input_vec[1] as f32,